@@ -0,0 +1,141 @@
+use std::{fmt, str::FromStr};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// max row number of excel sheet
+pub const MAX_ROW: usize = 1_048_576;
+/// max col number of excel sheet, that is, col `XFD`
+pub const MAX_COL: usize = 16_384;
+
+/// convert 1-based col to letters such as `A`, `ZZ` or `XFD`
+pub fn col_to_letters(col: usize) -> String {
+    let mut ret: Vec<u8> = vec![];
+    let mut col = col;
+    while 0 < col {
+        let rem = (col - 1) % 26;
+        ret.push(b'A' + rem as u8);
+        col = (col - 1) / 26;
+    }
+    ret.reverse();
+    String::from_utf8(ret).unwrap_or_default()
+}
+
+/// convert letters such as `A` to 1-based col. case insensitive
+///
+/// `None` when not letters or beyond col `XFD`
+pub fn letters_to_col(letters: &str) -> Option<usize> {
+    if letters.is_empty() || 3 < letters.len() {
+        return None;
+    }
+    let col = letters.chars().try_fold(0usize, |acc, c| {
+        c.is_ascii_alphabetic()
+            .then(|| acc * 26 + (c.to_ascii_uppercase() as u8 - b'A' + 1) as usize)
+    })?;
+    (col <= MAX_COL).then_some(col)
+}
+
+/// convert 1-based (row, col) to cell address str such as `A1`
+pub fn cell_pos_to_address(row: usize, col: usize) -> String {
+    format!("{}{}", col_to_letters(col), row)
+}
+
+/// convert cell address str such as `XFD1048576` to 1-based (row, col)
+///
+/// `$` of absolute reference is ignored. `None` when invalid or beyond sheet limits
+pub fn address_to_cell_pos(address: &str) -> Option<(usize, usize)> {
+    let address = address.replace('$', "");
+    let letters_len = address
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(address.len());
+    let (letters, digits) = address.split_at(letters_len);
+    let col = letters_to_col(letters)?;
+    let row = parse_row(digits)?;
+    Some((row, col))
+}
+
+/// parse 1-based row within sheet limits
+fn parse_row(digits: &str) -> Option<usize> {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let row: usize = digits.parse().ok()?;
+    (1..=MAX_ROW).contains(&row).then_some(row)
+}
+
+/// parse range with sheet name such as `Summary!A1:F200` or `'My Sheet'!A:C`
+pub fn parse_sheet_range(s: &str) -> Result<(String, CellRange), String> {
+    let (sheet, range) = s
+        .rsplit_once('!')
+        .ok_or_else(|| format!("Sheet name missing in range: {}", s))?;
+    let sheet = match sheet.strip_prefix('\'').and_then(|x| x.strip_suffix('\'')) {
+        Some(quoted) => quoted.replace("''", "'"),
+        None => sheet.to_owned(),
+    };
+    if sheet.is_empty() {
+        return Err(format!("Sheet name missing in range: {}", s));
+    }
+    Ok((sheet, range.parse()?))
+}
+
+/// cell range such as `A1:F200` in 1-based rows and cols, both ends inclusive
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CellRange {
+    pub start_row: usize,
+    pub start_col: usize,
+    pub end_row: usize,
+    pub end_col: usize,
+}
+
+impl CellRange {
+    /// whether range contains 1-based (row, col)
+    pub fn contains(&self, row: usize, col: usize) -> bool {
+        (self.start_row..=self.end_row).contains(&row)
+            && (self.start_col..=self.end_col).contains(&col)
+    }
+}
+
+impl FromStr for CellRange {
+    type Err = String;
+
+    /// parse `A1:F200`, single cell `B2`, whole cols `A:C` or whole rows `1:5`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid cell range: {}", s);
+
+        let (start, end) = s.split_once(':').unwrap_or((s, s));
+        let (start, end) = (start.trim().replace('$', ""), end.trim().replace('$', ""));
+        let (start_row, start_col, end_row, end_col) =
+            match (address_to_cell_pos(&start), address_to_cell_pos(&end)) {
+                (Some((start_row, start_col)), Some((end_row, end_col))) => {
+                    (start_row, start_col, end_row, end_col)
+                }
+                _ => match (letters_to_col(&start), letters_to_col(&end)) {
+                    (Some(start_col), Some(end_col)) => (1, start_col, MAX_ROW, end_col),
+                    _ => {
+                        let start_row = parse_row(&start).ok_or_else(invalid)?;
+                        let end_row = parse_row(&end).ok_or_else(invalid)?;
+                        (start_row, 1, end_row, MAX_COL)
+                    }
+                },
+            };
+
+        Ok(CellRange {
+            start_row: start_row.min(end_row),
+            start_col: start_col.min(end_col),
+            end_row: start_row.max(end_row),
+            end_col: start_col.max(end_col),
+        })
+    }
+}
+
+impl fmt::Display for CellRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            cell_pos_to_address(self.start_row, self.start_col),
+            cell_pos_to_address(self.end_row, self.end_col)
+        )
+    }
+}
@@ -0,0 +1,437 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use super::options::ColumnAlignment;
+
+/// rows whose equal cells are at least this ratio are regarded as the same row modified
+const MODIFIED_ROW_SIMILARITY: f64 = 0.5;
+/// cols sharing values at least this ratio are regarded as the same col
+const SAME_COLUMN_SIMILARITY: f64 = 0.5;
+
+/// pairs of old / new indexes to compare. `None` means inserted or deleted
+pub type IndexPairs = Vec<(Option<u32>, Option<u32>)>;
+
+/// cells to compare: pairs of old / new rows and cols
+pub struct Alignment {
+    pub rows: IndexPairs,
+    pub cols: IndexPairs,
+}
+
+impl Alignment {
+    /// rows existing in both old and new
+    pub fn matched_rows(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        matched_pairs(&self.rows)
+    }
+
+    /// cols existing in both old and new
+    pub fn matched_cols(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        matched_pairs(&self.cols)
+    }
+
+    /// matched cols whose order differs from the old one
+    pub fn moved_cols(&self) -> Vec<(u32, u32)> {
+        moved_pairs(self.matched_cols().collect())
+    }
+
+    /// index to look up positions on the other side, built once per sheet
+    pub fn counterparts(&self) -> Counterparts {
+        let index = |pairs: &IndexPairs, is_old: bool| -> HashMap<u32, Option<u32>> {
+            pairs
+                .iter()
+                .map(|&(old, new)| if is_old { (old, new) } else { (new, old) })
+                .filter_map(|(from, to)| from.map(|from| (from, to)))
+                .collect()
+        };
+        Counterparts {
+            old_rows: index(&self.rows, true),
+            old_cols: index(&self.cols, true),
+            new_rows: index(&self.rows, false),
+            new_cols: index(&self.cols, false),
+        }
+    }
+}
+
+/// old to new, and new to old, rows and cols of alignment
+pub struct Counterparts {
+    old_rows: HashMap<u32, Option<u32>>,
+    old_cols: HashMap<u32, Option<u32>>,
+    new_rows: HashMap<u32, Option<u32>>,
+    new_cols: HashMap<u32, Option<u32>>,
+}
+
+impl Counterparts {
+    /// (row, col) on the other side. `None` when row / col is inserted / deleted, and the same
+    /// position when out of alignment
+    pub fn pos(&self, (row, col): (u32, u32), is_old: bool) -> Option<(u32, u32)> {
+        let (rows, cols) = if is_old {
+            (&self.old_rows, &self.old_cols)
+        } else {
+            (&self.new_rows, &self.new_cols)
+        };
+        let counterpart =
+            |index: &HashMap<u32, Option<u32>>, x: u32| index.get(&x).copied().unwrap_or(Some(x));
+        counterpart(rows, row).zip(counterpart(cols, col))
+    }
+}
+
+/// pairs out of the longest run keeping the old order, that is, moved ones
+pub fn moved_pairs(mut pairs: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    pairs.sort();
+    let mut by_new = pairs.clone();
+    by_new.sort_by_key(|(_, new)| *new);
+
+    let old_order: Vec<u64> = pairs.iter().map(|(old, _)| *old as u64).collect();
+    let new_order: Vec<u64> = by_new.iter().map(|(old, _)| *old as u64).collect();
+    let stable: Vec<usize> = myers_matches(&old_order, &new_order)
+        .into_iter()
+        .map(|(x, _)| x)
+        .collect();
+
+    pairs
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !stable.contains(i))
+        .map(|(_, x)| x)
+        .collect()
+}
+
+/// pairs both of which exist
+pub fn matched_pairs(pairs: &IndexPairs) -> impl Iterator<Item = (u32, u32)> + '_ {
+    pairs.iter().filter_map(|pair| match *pair {
+        (Some(old), Some(new)) => Some((old, new)),
+        _ => None,
+    })
+}
+
+/// pair same indexes in range
+pub fn positional_pairs(start: u32, end: u32) -> IndexPairs {
+    (start..end).map(|x| (Some(x), Some(x))).collect()
+}
+
+/// align lines by their content so that insertion / deletion doesn't shift the following lines
+///
+/// each line is cell texts. lines equal are matched by Myers' algorithm and then,
+/// between matched lines, deleted and inserted lines similar enough are paired as modified
+pub fn align_lines(old: &[Vec<String>], new: &[Vec<String>], offset: u32) -> IndexPairs {
+    let old_hashes: Vec<u64> = old.iter().map(line_hash).collect();
+    let new_hashes: Vec<u64> = new.iter().map(line_hash).collect();
+    let matches = myers_matches(&old_hashes, &new_hashes);
+
+    let mut ret: IndexPairs = vec![];
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for (old_matched, new_matched) in matches
+        .into_iter()
+        .chain(std::iter::once((old.len(), new.len())))
+    {
+        let gap = pair_similar_lines(old, new, old_pos..old_matched, new_pos..new_matched);
+        ret.extend(gap);
+        if old_matched < old.len() && new_matched < new.len() {
+            ret.push((Some(old_matched as u32), Some(new_matched as u32)));
+        }
+        old_pos = old_matched + 1;
+        new_pos = new_matched + 1;
+    }
+
+    ret.into_iter()
+        .map(|(old, new)| (old.map(|x| x + offset), new.map(|x| x + offset)))
+        .collect()
+}
+
+/// align cols by header text (first cell) and / or content so that insertion / deletion / move
+/// doesn't shift the other cols
+///
+/// each col is cell texts. the result is in new col order followed by deleted cols.
+/// empty cols are left out as there is nothing to compare
+pub fn align_columns(
+    old: &[Vec<String>],
+    new: &[Vec<String>],
+    by: ColumnAlignment,
+    offset: u32,
+) -> IndexPairs {
+    let is_empty = |col: &Vec<String>| col.iter().all(|x| x.is_empty());
+    let mut old_matched: Vec<Option<usize>> = vec![None; new.len()];
+    let mut is_old_matched: Vec<bool> = old.iter().map(is_empty).collect();
+    let is_new_empty: Vec<bool> = new.iter().map(is_empty).collect();
+
+    if matches!(
+        by,
+        ColumnAlignment::Header | ColumnAlignment::HeaderAndContent
+    ) {
+        for (new_index, new_col) in new.iter().enumerate() {
+            let header = new_col.first().map(|x| x.as_str()).unwrap_or_default();
+            if header.is_empty() {
+                continue;
+            }
+            let found = old.iter().enumerate().position(|(old_index, old_col)| {
+                !is_old_matched[old_index] && old_col.first().is_some_and(|x| x == header)
+            });
+            if let Some(old_index) = found {
+                old_matched[new_index] = Some(old_index);
+                is_old_matched[old_index] = true;
+            }
+        }
+    }
+
+    if matches!(
+        by,
+        ColumnAlignment::Content | ColumnAlignment::HeaderAndContent
+    ) {
+        let mut candidates: Vec<(f64, usize, usize)> = vec![];
+        for (new_index, new_col) in new.iter().enumerate() {
+            if old_matched[new_index].is_some() {
+                continue;
+            }
+            for (old_index, old_col) in old.iter().enumerate() {
+                if is_old_matched[old_index] {
+                    continue;
+                }
+                // header is excluded
+                let similarity = values_similarity(
+                    old_col.get(1..).unwrap_or_default(),
+                    new_col.get(1..).unwrap_or_default(),
+                );
+                if similarity >= SAME_COLUMN_SIMILARITY {
+                    candidates.push((similarity, old_index, new_index));
+                }
+            }
+        }
+        // most similar first, and nearest first on tie
+        candidates.sort_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then_with(|| a.1.abs_diff(a.2).cmp(&b.1.abs_diff(b.2)))
+        });
+        for (_, old_index, new_index) in candidates {
+            if !is_old_matched[old_index] && old_matched[new_index].is_none() {
+                old_matched[new_index] = Some(old_index);
+                is_old_matched[old_index] = true;
+            }
+        }
+    }
+
+    let mut ret: IndexPairs = old_matched
+        .into_iter()
+        .enumerate()
+        .filter(|(new_index, old_index)| old_index.is_some() || !is_new_empty[*new_index])
+        .map(|(new_index, old_index)| (old_index.map(|x| x as u32), Some(new_index as u32)))
+        .collect();
+    ret.extend(
+        is_old_matched
+            .into_iter()
+            .enumerate()
+            .filter(|(_, is_matched)| !is_matched)
+            .map(|(old_index, _)| (Some(old_index as u32), None)),
+    );
+
+    ret.into_iter()
+        .map(|(old, new)| (old.map(|x| x + offset), new.map(|x| x + offset)))
+        .collect()
+}
+
+/// ratio of non-empty values shared by both, regardless of their order
+pub fn values_similarity(old: &[String], new: &[String]) -> f64 {
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    let old_values: Vec<&str> = old
+        .iter()
+        .map(|x| x.as_str())
+        .filter(|x| !x.is_empty())
+        .collect();
+    let new_values: Vec<&str> = new
+        .iter()
+        .map(|x| x.as_str())
+        .filter(|x| !x.is_empty())
+        .collect();
+    old_values
+        .iter()
+        .for_each(|x| *counts.entry(x).or_default() += 1);
+
+    let mut shared = 0;
+    for x in new_values.iter() {
+        if let Some(count) = counts.get_mut(x) {
+            if 0 < *count {
+                *count -= 1;
+                shared += 1;
+            }
+        }
+    }
+
+    let total = old_values.len().max(new_values.len());
+    if total == 0 {
+        0.0
+    } else {
+        shared as f64 / total as f64
+    }
+}
+
+/// hash of line content
+fn line_hash(line: &Vec<String>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    line.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// ratio of equal cells in non-empty cells
+fn similarity(old: &[String], new: &[String]) -> f64 {
+    let len = old.len().max(new.len());
+    let (mut equal, mut non_empty) = (0, 0);
+    for i in 0..len {
+        let old = old.get(i).map(|x| x.as_str()).unwrap_or_default();
+        let new = new.get(i).map(|x| x.as_str()).unwrap_or_default();
+        if old.is_empty() && new.is_empty() {
+            continue;
+        }
+        non_empty += 1;
+        if old == new {
+            equal += 1;
+        }
+    }
+    if non_empty == 0 {
+        1.0
+    } else {
+        equal as f64 / non_empty as f64
+    }
+}
+
+/// pair deleted and inserted lines between matched ones, keeping their order
+fn pair_similar_lines(
+    old: &[Vec<String>],
+    new: &[Vec<String>],
+    old_range: std::ops::Range<usize>,
+    new_range: std::ops::Range<usize>,
+) -> IndexPairs {
+    let mut ret: IndexPairs = vec![];
+    let mut new_pos = new_range.start;
+    for old_index in old_range {
+        let found = (new_pos..new_range.end).find(|&new_index| {
+            similarity(&old[old_index], &new[new_index]) >= MODIFIED_ROW_SIMILARITY
+        });
+        match found {
+            Some(new_index) => {
+                ret.extend((new_pos..new_index).map(|x| (None, Some(x as u32))));
+                ret.push((Some(old_index as u32), Some(new_index as u32)));
+                new_pos = new_index + 1;
+            }
+            None => ret.push((Some(old_index as u32), None)),
+        }
+    }
+    ret.extend((new_pos..new_range.end).map(|x| (None, Some(x as u32))));
+    ret
+}
+
+/// indexes of equal items in longest common subsequence by Myers' algorithm
+///
+/// linear space variant, which divides at middle snake instead of keeping trace of each step
+fn myers_matches(old: &[u64], new: &[u64]) -> Vec<(usize, usize)> {
+    // items only in either side never match, so they are left out beforehand
+    let old_items: HashSet<u64> = old.iter().copied().collect();
+    let new_items: HashSet<u64> = new.iter().copied().collect();
+    let old_indexes: Vec<usize> = (0..old.len())
+        .filter(|&i| new_items.contains(&old[i]))
+        .collect();
+    let new_indexes: Vec<usize> = (0..new.len())
+        .filter(|&i| old_items.contains(&new[i]))
+        .collect();
+    let a: Vec<u64> = old_indexes.iter().map(|&i| old[i]).collect();
+    let b: Vec<u64> = new_indexes.iter().map(|&i| new[i]).collect();
+
+    let mut matches: Vec<(usize, usize)> = vec![];
+    push_myers_matches(&a, &b, (0, 0), &mut matches);
+    matches
+        .into_iter()
+        .map(|(x, y)| (old_indexes[x], new_indexes[y]))
+        .collect()
+}
+
+/// push matches of `a` and `b` in order, shifted by `offset`
+fn push_myers_matches(
+    a: &[u64],
+    b: &[u64],
+    offset: (usize, usize),
+    matches: &mut Vec<(usize, usize)>,
+) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    matches.extend((0..prefix).map(|i| (offset.0 + i, offset.1 + i)));
+
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+    if !a_mid.is_empty() && !b_mid.is_empty() {
+        // both ends differ, so edit distance is 2 or more and both halves are smaller
+        let (x, y, u, v) = middle_snake(a_mid, b_mid);
+        let (mid_x, mid_y) = (offset.0 + prefix, offset.1 + prefix);
+        push_myers_matches(&a_mid[..x], &b_mid[..y], (mid_x, mid_y), matches);
+        matches.extend((0..u - x).map(|i| (mid_x + x + i, mid_y + y + i)));
+        push_myers_matches(&a_mid[u..], &b_mid[v..], (mid_x + u, mid_y + v), matches);
+    }
+
+    let (a_end, b_end) = (offset.0 + a.len() - suffix, offset.1 + b.len() - suffix);
+    matches.extend((0..suffix).map(|i| (a_end + i, b_end + i)));
+}
+
+/// start and end (x, y) of snake in the middle of shortest edit path, found by searching
+/// forward from the start and backward from the end at the same time
+fn middle_snake(a: &[u64], b: &[u64]) -> (usize, usize, usize, usize) {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let delta = n - m;
+    let is_odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2 + 1;
+    // furthest x on each diagonal k = x - y. backward one counts x from the end
+    let mut forward = vec![0isize; 2 * max as usize + 1];
+    let mut backward = vec![0isize; 2 * max as usize + 1];
+    let index = |k: isize| (k + max) as usize;
+    let next_x = |v: &[isize], k: isize, d: isize| {
+        if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            v[index(k + 1)]
+        } else {
+            v[index(k - 1)] + 1
+        }
+    };
+
+    for d in 0..max {
+        for k in (-d..=d).step_by(2) {
+            let start_x = next_x(&forward, k, d);
+            let start_y = start_x - k;
+            let (mut x, mut y) = (start_x, start_y);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index(k)] = x;
+            // backward diagonal of the same one is delta - k
+            let backward_k = delta - k;
+            if is_odd
+                && (-(d - 1)..=d - 1).contains(&backward_k)
+                && x + backward[index(backward_k)] >= n
+            {
+                return (start_x as usize, start_y as usize, x as usize, y as usize);
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let start_x = next_x(&backward, k, d);
+            let start_y = start_x - k;
+            let (mut x, mut y) = (start_x, start_y);
+            while x < n && y < m && a[(n - 1 - x) as usize] == b[(m - 1 - y) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[index(k)] = x;
+            let forward_k = delta - k;
+            if !is_odd && (-d..=d).contains(&forward_k) && x + forward[index(forward_k)] >= n {
+                return (
+                    (n - x) as usize,
+                    (m - y) as usize,
+                    (n - start_x) as usize,
+                    (m - start_y) as usize,
+                );
+            }
+        }
+    }
+    unreachable!("paths meet within half of edit distance")
+}
@@ -0,0 +1,64 @@
+use calamine::{Cell, Data, Range};
+use csv::ReaderBuilder;
+use encoding_rs::Encoding;
+
+use super::options::CsvOptions;
+
+/// parse csv / tsv bytes into cell range
+///
+/// fields are typed so that they can be compared with workbook cells:
+/// numbers as float, `TRUE` / `FALSE` as bool and the others as string
+pub fn read_delimited(
+    bytes: &[u8],
+    delimiter: u8,
+    options: &CsvOptions,
+) -> Result<Range<Data>, String> {
+    let encoding = Encoding::for_label(options.encoding.as_bytes())
+        .ok_or_else(|| format!("unknown encoding: {}", options.encoding))?;
+    let (text, _, _) = encoding.decode(bytes);
+
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quote(options.quote)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+
+    let mut cells: Vec<Cell<Data>> = vec![];
+    for (row, record) in reader.records().enumerate() {
+        let record = record.map_err(|err| err.to_string())?;
+        for (col, field) in record.iter().enumerate() {
+            let value = field_to_data(field);
+            if value != Data::Empty {
+                cells.push(Cell::new((row as u32, col as u32), value));
+            }
+        }
+    }
+
+    Ok(Range::from_sparse(cells))
+}
+
+/// type csv field
+fn field_to_data(field: &str) -> Data {
+    if field.is_empty() {
+        return Data::Empty;
+    }
+    if field.eq_ignore_ascii_case("TRUE") {
+        return Data::Bool(true);
+    }
+    if field.eq_ignore_ascii_case("FALSE") {
+        return Data::Bool(false);
+    }
+
+    let is_numeric_text = field
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E'));
+    // `007` such as id or zip code is text, while `0` and `0.5` are numbers
+    let unsigned = field.trim_start_matches(['+', '-']);
+    let is_zero_padded =
+        unsigned.starts_with('0') && unsigned[1..].starts_with(|c: char| c.is_ascii_digit());
+    match field.parse::<f64>() {
+        Ok(x) if is_numeric_text && !is_zero_padded && x.is_finite() => Data::Float(x),
+        _ => Data::String(field.to_owned()),
+    }
+}
@@ -1,16 +1,66 @@
-use std::{fmt, fs::File, io::BufReader};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{Cursor, Read, Seek},
+};
 
-use calamine::{open_workbook, Data, Reader, Xlsx};
+use calamine::{Data, Range};
+use chrono::{NaiveDateTime, NaiveTime};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use super::utils::{cell_pos_to_address, diff_range, filter_same_name_sheets};
+use super::{
+    address::{cell_pos_to_address, CellRange},
+    align::{
+        align_columns, align_lines, matched_pairs, moved_pairs, positional_pairs,
+        values_similarity, Alignment, Counterparts,
+    },
+    error::Error,
+    formula::normalize_formula,
+    options::{
+        ComparisonPolicy, DiffOptions, FormulaComparison, KeyColumn, NumericTolerance, SheetFilter,
+        TextComparison,
+    },
+    package::{CellStyle, DefinedName, SheetStyles, Table},
+    record::{cell_text, Records},
+    utils::{
+        crop_range, data_to_string, diff_range, duration_to_iso, filter_same_name_sheets,
+        is_time_only, temporal_value, union_range,
+    },
+    workbook::{SheetVisibility, Workbook},
+};
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum CellDiffKind {
+    /// value changed. hard-coded value edit unless formula changed too
     Value,
+    /// formula edit
     Formula,
+    /// type changed while text is the same, such as number `1` to text `1`
+    Type,
+    /// computed result changed while formula is the same, that is, some input changed
+    Result,
+    /// formula replaced by constant value, such as value pasted over formula
+    FormulaToConstant,
+    /// constant value replaced by formula
+    ConstantToFormula,
+    /// comment added, edited, removed or resolved
+    Comment,
+    /// formatting changed: number format, font, fill, borders or alignment
+    Style,
+}
+
+impl CellDiffKind {
+    /// whether formula changed
+    pub fn is_formula_change(&self) -> bool {
+        matches!(
+            self,
+            CellDiffKind::Formula
+                | CellDiffKind::FormulaToConstant
+                | CellDiffKind::ConstantToFormula
+        )
+    }
 }
 
 impl fmt::Display for CellDiffKind {
@@ -18,6 +68,127 @@ impl fmt::Display for CellDiffKind {
         match self {
             CellDiffKind::Formula => write!(f, "formula"),
             CellDiffKind::Value => write!(f, "value"),
+            CellDiffKind::Type => write!(f, "type"),
+            CellDiffKind::Result => write!(f, "result"),
+            CellDiffKind::FormulaToConstant => write!(f, "formula to constant"),
+            CellDiffKind::ConstantToFormula => write!(f, "constant to formula"),
+            CellDiffKind::Comment => write!(f, "comment"),
+            CellDiffKind::Style => write!(f, "style"),
+        }
+    }
+}
+
+/// cell value type
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CellType {
+    Empty,
+    Int,
+    Float,
+    String,
+    Bool,
+    DateTime,
+    Duration,
+    Error,
+}
+
+impl fmt::Display for CellType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CellType::Empty => write!(f, "empty"),
+            CellType::Int => write!(f, "integer"),
+            CellType::Float => write!(f, "float"),
+            CellType::String => write!(f, "text"),
+            CellType::Bool => write!(f, "boolean"),
+            CellType::DateTime => write!(f, "date time"),
+            CellType::Duration => write!(f, "duration"),
+            CellType::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// typed cell value. `to_string()` gives the same text as `CellDiff` `old` / `new`
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CellValue {
+    Empty,
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bool(bool),
+    /// date or date time, in local time as excel has no time zone
+    DateTime(NaiveDateTime),
+    /// time of day without date
+    Time(NaiveTime),
+    /// duration in milliseconds
+    Duration(i64),
+    /// error such as `#DIV/0!`
+    Error(String),
+}
+
+impl CellValue {
+    /// value type
+    pub fn cell_type(&self) -> CellType {
+        match self {
+            CellValue::Empty => CellType::Empty,
+            CellValue::Int(_) => CellType::Int,
+            CellValue::Float(_) => CellType::Float,
+            CellValue::String(_) => CellType::String,
+            CellValue::Bool(_) => CellType::Bool,
+            CellValue::DateTime(_) | CellValue::Time(_) => CellType::DateTime,
+            CellValue::Duration(_) => CellType::Duration,
+            CellValue::Error(_) => CellType::Error,
+        }
+    }
+}
+
+impl fmt::Display for CellValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CellValue::Empty => Ok(()),
+            CellValue::Int(x) => write!(f, "{}", x),
+            CellValue::Float(x) => write!(f, "{}", x),
+            CellValue::String(x) | CellValue::Error(x) => write!(f, "{}", x),
+            CellValue::Bool(x) => write!(f, "{}", x),
+            // ISO 8601: `2024-01-31`, `2024-01-31T12:00:00`, `12:00:00` or `PT36H30M`
+            CellValue::DateTime(x) if x.time() == NaiveTime::MIN => {
+                write!(f, "{}", x.format("%Y-%m-%d"))
+            }
+            CellValue::DateTime(x) => write!(f, "{}", x.format("%Y-%m-%dT%H:%M:%S%.f")),
+            CellValue::Time(x) => write!(f, "{}", x.format("%H:%M:%S%.f")),
+            CellValue::Duration(x) => write!(f, "{}", duration_to_iso(*x)),
+        }
+    }
+}
+
+impl From<&Data> for CellValue {
+    fn from(data: &Data) -> Self {
+        match data {
+            Data::Empty => CellValue::Empty,
+            Data::Int(x) => CellValue::Int(*x),
+            Data::Float(x) => CellValue::Float(*x),
+            Data::String(x) => CellValue::String(x.to_owned()),
+            Data::Bool(x) => CellValue::Bool(*x),
+            Data::DateTime(_) | Data::DateTimeIso(_) | Data::DurationIso(_) => {
+                temporal_value(data).unwrap_or_else(|| CellValue::String(data.to_string()))
+            }
+            Data::Error(_) => CellValue::Error(data.to_string()),
+        }
+    }
+}
+
+impl From<&Data> for CellType {
+    fn from(data: &Data) -> Self {
+        match data {
+            Data::Empty => CellType::Empty,
+            Data::Int(_) => CellType::Int,
+            Data::Float(_) => CellType::Float,
+            Data::String(_) => CellType::String,
+            Data::Bool(_) => CellType::Bool,
+            Data::DateTime(x) if x.is_duration() => CellType::Duration,
+            Data::DateTime(_) | Data::DateTimeIso(_) => CellType::DateTime,
+            Data::DurationIso(_) => CellType::Duration,
+            Data::Error(_) => CellType::Error,
         }
     }
 }
@@ -29,21 +200,381 @@ pub struct Diff {
     pub old_filepath: String,
     pub new_filepath: String,
     pub sheet_diff: Vec<SheetDiff>,
+    /// added / removed / repointed defined names (named ranges)
+    pub defined_names: Vec<DefinedNameDiff>,
+    /// added / removed / changed excel tables
+    pub tables: Vec<TableDiff>,
     pub cell_diffs: Vec<SheetCellDiff>,
+    /// sheets failed to be read. cell diffs of them are missing
+    pub sheet_errors: Vec<Error>,
 }
 
+/// sheet removed (`new` is `None`), added (`old` is `None`), or renamed, moved or
+/// shown / hidden (both are `Some`)
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SheetDiff {
     pub old: Option<String>,
     pub new: Option<String>,
+    /// ratio of cell values shared by renamed sheets
+    pub similarity: Option<f64>,
+    /// 1-based tab positions of moved sheet
+    pub old_position: Option<usize>,
+    pub new_position: Option<usize>,
+    /// visibility changed
+    pub old_visibility: Option<SheetVisibility>,
+    pub new_visibility: Option<SheetVisibility>,
+}
+
+impl SheetDiff {
+    /// init with names only
+    fn new(old: Option<&str>, new: Option<&str>) -> Self {
+        SheetDiff {
+            old: old.map(|x| x.to_owned()),
+            new: new.map(|x| x.to_owned()),
+            similarity: None,
+            old_position: None,
+            new_position: None,
+            old_visibility: None,
+            new_visibility: None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DefinedNameDiffKind {
+    Added,
+    Removed,
+    /// reference changed
+    Changed,
+}
+
+impl fmt::Display for DefinedNameDiffKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DefinedNameDiffKind::Added => write!(f, "added"),
+            DefinedNameDiffKind::Removed => write!(f, "removed"),
+            DefinedNameDiffKind::Changed => write!(f, "changed"),
+        }
+    }
+}
+
+/// defined name (named range) diff
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DefinedNameDiff {
+    pub name: String,
+    /// sheet name for sheet-scoped name. `None` for workbook-scoped one
+    pub scope: Option<String>,
+    pub kind: DefinedNameDiffKind,
+    /// references such as `Sheet1!$B$2`
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TableDiffKind {
+    Added,
+    Removed,
+    /// renamed, resized, moved to other sheet, or its cols or rows changed
+    Changed,
+}
+
+impl fmt::Display for TableDiffKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableDiffKind::Added => write!(f, "added"),
+            TableDiffKind::Removed => write!(f, "removed"),
+            TableDiffKind::Changed => write!(f, "changed"),
+        }
+    }
+}
+
+/// excel table (list object) diff
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TableDiff {
+    pub kind: TableDiffKind,
+    pub old_name: Option<String>,
+    pub new_name: Option<String>,
+    pub old_sheet: Option<String>,
+    pub new_sheet: Option<String>,
+    /// ranges including header row
+    pub old_range: Option<CellRange>,
+    pub new_range: Option<CellRange>,
+    /// added / removed / renamed cols
+    pub columns: Vec<TableColumnDiff>,
+    /// added / removed / changed rows. collected only when table is keyed
+    pub records: Vec<RecordDiff>,
+    /// keys shared by several rows, which are left out of `records`
+    pub duplicate_keys: Vec<DuplicateKey>,
+}
+
+impl TableDiff {
+    /// whether table name changed
+    pub fn is_renamed(&self) -> bool {
+        self.kind == TableDiffKind::Changed && self.old_name != self.new_name
+    }
+
+    /// whether table range changed
+    pub fn is_resized(&self) -> bool {
+        self.kind == TableDiffKind::Changed
+            && self
+                .old_range
+                .map(|x| (x.end_row - x.start_row, x.end_col - x.start_col))
+                != self
+                    .new_range
+                    .map(|x| (x.end_row - x.start_row, x.end_col - x.start_col))
+    }
+
+    /// whether table moved to other position or sheet
+    pub fn is_moved(&self) -> bool {
+        self.kind == TableDiffKind::Changed
+            && (self.old_sheet != self.new_sheet
+                || self.old_range.map(|x| (x.start_row, x.start_col))
+                    != self.new_range.map(|x| (x.start_row, x.start_col)))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TableColumnDiffKind {
+    Added,
+    Removed,
+    Renamed,
+}
+
+impl fmt::Display for TableColumnDiffKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableColumnDiffKind::Added => write!(f, "added"),
+            TableColumnDiffKind::Removed => write!(f, "removed"),
+            TableColumnDiffKind::Renamed => write!(f, "renamed"),
+        }
+    }
+}
+
+/// table col diff
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TableColumnDiff {
+    pub kind: TableColumnDiffKind,
+    /// col names
+    pub old: Option<String>,
+    pub new: Option<String>,
 }
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SheetCellDiff {
     pub sheet: String,
+    /// sheet name in new file when it differs from `sheet`, e.g. on comparing csv with sheet
+    pub new_sheet: Option<String>,
+    /// inserted / deleted / moved cols. collected only when cols are aligned
+    pub columns: Vec<ColumnDiff>,
+    /// inserted / deleted rows. collected only when rows are aligned
+    pub rows: Vec<RowDiff>,
     pub cells: Vec<CellDiff>,
+    /// added / removed / changed records. collected instead of the others when sheet is keyed
+    pub records: Vec<RecordDiff>,
+    /// keys shared by several records, which are left out of `records`
+    pub duplicate_keys: Vec<DuplicateKey>,
+    /// added / removed / resized merged cell ranges
+    pub merged_cells: Vec<MergedCellDiff>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ColumnDiffKind {
+    Inserted,
+    Deleted,
+    Moved,
+}
+
+impl fmt::Display for ColumnDiffKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnDiffKind::Inserted => write!(f, "inserted"),
+            ColumnDiffKind::Deleted => write!(f, "deleted"),
+            ColumnDiffKind::Moved => write!(f, "moved"),
+        }
+    }
+}
+
+/// value and formula diffs of the same cell together
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CombinedCellDiff {
+    pub row: usize,
+    pub col: usize,
+    pub addr: String,
+    pub new_row: usize,
+    pub new_col: usize,
+    pub new_addr: String,
+    /// how the cell changed: kind of `formula` if formula changed, or else kind of `value`,
+    /// or else comment
+    pub kind: CellDiffKind,
+    /// value, type or result diff, if any
+    pub value: Option<CellDiff>,
+    /// formula diff, if any
+    pub formula: Option<CellDiff>,
+    /// comment diff, if any
+    pub comment: Option<CellDiff>,
+}
+
+impl SheetCellDiff {
+    /// cell diffs combined by cell, in the order of `cells`. style diff stays apart
+    pub fn combined_cells(&self) -> Vec<CombinedCellDiff> {
+        let mut ret: Vec<CombinedCellDiff> = vec![];
+        // index in ret by old and new addresses, and whether style diff
+        let mut indexes: HashMap<(&str, &str, bool), usize> = HashMap::new();
+        for cell in self.cells.iter() {
+            let key = (
+                cell.addr.as_str(),
+                cell.new_addr.as_str(),
+                cell.kind == CellDiffKind::Style,
+            );
+            let index = *indexes.entry(key).or_insert_with(|| {
+                ret.push(CombinedCellDiff {
+                    row: cell.row,
+                    col: cell.col,
+                    addr: cell.addr.to_owned(),
+                    new_row: cell.new_row,
+                    new_col: cell.new_col,
+                    new_addr: cell.new_addr.to_owned(),
+                    kind: cell.kind.clone(),
+                    value: None,
+                    formula: None,
+                    comment: None,
+                });
+                ret.len() - 1
+            });
+            let combined = &mut ret[index];
+            match cell.kind {
+                _ if cell.kind.is_formula_change() => {
+                    combined.kind = cell.kind.clone();
+                    combined.formula = Some(cell.clone());
+                }
+                CellDiffKind::Comment => {
+                    if combined.value.is_none() && combined.formula.is_none() {
+                        combined.kind = cell.kind.clone();
+                    }
+                    combined.comment = Some(cell.clone());
+                }
+                // texts in value as the other diffs without formula
+                CellDiffKind::Style => combined.value = Some(cell.clone()),
+                _ => {
+                    if combined.formula.is_none() {
+                        combined.kind = cell.kind.clone();
+                    }
+                    combined.value = Some(cell.clone());
+                }
+            }
+        }
+        ret
+    }
+}
+
+/// col inserted, deleted or moved. `old` / `new` are header text
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ColumnDiff {
+    pub kind: ColumnDiffKind,
+    pub old_col: Option<usize>,
+    pub new_col: Option<usize>,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// row inserted (`old_row` is `None`) or deleted (`new_row` is `None`)
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RowDiff {
+    pub old_row: Option<usize>,
+    pub new_row: Option<usize>,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecordDiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl fmt::Display for RecordDiffKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordDiffKind::Added => write!(f, "added"),
+            RecordDiffKind::Removed => write!(f, "removed"),
+            RecordDiffKind::Changed => write!(f, "changed"),
+        }
+    }
+}
+
+/// record matched by key. `old` / `new` are record text, set when added or removed
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecordDiff {
+    pub kind: RecordDiffKind,
+    /// key values joined with `, `
+    pub key: String,
+    pub old_row: Option<usize>,
+    pub new_row: Option<usize>,
+    pub old: Option<String>,
+    pub new: Option<String>,
+    /// changed fields of changed record
+    pub fields: Vec<FieldDiff>,
+}
+
+/// field changed in record. field is header text, or col letters when header is empty
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FieldDiff {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// key shared by several records in old and / or new sheet
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DuplicateKey {
+    pub key: String,
+    pub old_rows: Vec<usize>,
+    pub new_rows: Vec<usize>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MergedCellDiffKind {
+    Added,
+    Removed,
+    /// same top-left cell with other extent
+    Resized,
+}
+
+impl fmt::Display for MergedCellDiffKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergedCellDiffKind::Added => write!(f, "added"),
+            MergedCellDiffKind::Removed => write!(f, "removed"),
+            MergedCellDiffKind::Resized => write!(f, "resized"),
+        }
+    }
+}
+
+/// merged cell range diff
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MergedCellDiff {
+    pub kind: MergedCellDiffKind,
+    pub old: Option<CellRange>,
+    pub new: Option<CellRange>,
 }
 
 #[derive(Clone, Debug)]
@@ -52,43 +583,131 @@ pub struct CellDiff {
     pub row: usize,
     pub col: usize,
     pub addr: String,
+    /// position in new sheet. differs from the old one when rows are aligned
+    pub new_row: usize,
+    pub new_col: usize,
+    pub new_addr: String,
     pub kind: CellDiffKind,
     pub old: Option<String>,
     pub new: Option<String>,
+    /// value types. `None` for formula diff except formula to / from constant
+    pub old_type: Option<CellType>,
+    pub new_type: Option<CellType>,
+    /// typed values. `None` for formula diff except formula to / from constant
+    pub old_value: Option<CellValue>,
+    pub new_value: Option<CellValue>,
 }
 
 impl Diff {
     /// init
+    ///
+    /// panics when either workbook cannot be opened. use `try_new` to handle the error
     pub fn new(old_filepath: &str, new_filepath: &str) -> Self {
+        Self::try_new(old_filepath, new_filepath).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// init, returning error when either workbook cannot be opened
+    ///
+    /// sheets failed to be read are recorded in `sheet_errors` instead of being an error
+    pub fn try_new(old_filepath: &str, new_filepath: &str) -> Result<Self, Error> {
+        Self::try_new_with_options(old_filepath, new_filepath, &DiffOptions::default())
+    }
+
+    /// init with options, returning error when either workbook cannot be opened
+    ///
+    /// file path may be csv / tsv, and `book.xlsx#Sheet1` selects one sheet to compare
+    pub fn try_new_with_options(
+        old_filepath: &str,
+        new_filepath: &str,
+        options: &DiffOptions,
+    ) -> Result<Self, Error> {
+        let sheet_filter = SheetFilter::new(&options.sheets)?;
+        let old_workbook = Workbook::open(old_filepath, options)?;
+        let new_workbook = Workbook::open(new_filepath, options)?;
+
+        Ok(Self::from_workbooks(
+            old_workbook,
+            new_workbook,
+            (old_filepath, new_filepath),
+            options,
+            &sheet_filter,
+        ))
+    }
+
+    /// init from readers such as uploaded files. labels are used in place of file paths
+    ///
+    /// format is detected by magic bytes, or by label extension as fallback
+    pub fn from_readers<RO, RN>(old: RO, new: RN, labels: (&str, &str)) -> Result<Self, Error>
+    where
+        RO: Read + Seek,
+        RN: Read + Seek,
+    {
+        Self::from_readers_with_options(old, new, labels, &DiffOptions::default())
+    }
+
+    /// init from readers with options
+    pub fn from_readers_with_options<RO, RN>(
+        old: RO,
+        new: RN,
+        labels: (&str, &str),
+        options: &DiffOptions,
+    ) -> Result<Self, Error>
+    where
+        RO: Read + Seek,
+        RN: Read + Seek,
+    {
+        let sheet_filter = SheetFilter::new(&options.sheets)?;
+        let old_workbook = Workbook::from_reader(old, labels.0, options)?;
+        let new_workbook = Workbook::from_reader(new, labels.1, options)?;
+
+        Ok(Self::from_workbooks(
+            old_workbook,
+            new_workbook,
+            labels,
+            options,
+            &sheet_filter,
+        ))
+    }
+
+    /// init from in-memory bytes
+    pub fn from_bytes(old: &[u8], new: &[u8], labels: (&str, &str)) -> Result<Self, Error> {
+        Self::from_readers(Cursor::new(old), Cursor::new(new), labels)
+    }
+
+    /// collect diff between opened workbooks
+    fn from_workbooks(
+        mut old_workbook: Workbook,
+        mut new_workbook: Workbook,
+        labels: (&str, &str),
+        options: &DiffOptions,
+        sheet_filter: &SheetFilter,
+    ) -> Self {
         let mut ret = Diff {
-            old_filepath: old_filepath.to_owned(),
-            new_filepath: new_filepath.to_owned(),
+            old_filepath: labels.0.to_owned(),
+            new_filepath: labels.1.to_owned(),
             sheet_diff: vec![],
+            defined_names: vec![],
+            tables: vec![],
             cell_diffs: vec![],
+            sheet_errors: vec![],
         };
 
-        ret.collect_diff();
+        ret.collect_diff(&mut old_workbook, &mut new_workbook, options, sheet_filter);
 
         ret.cell_diffs.sort_by(|a, b| a.sheet.cmp(&b.sheet));
-
-        let mut merged_cell_diffs: Vec<SheetCellDiff> = vec![];
-        ret.cell_diffs.iter().for_each(|a| {
-            let found = merged_cell_diffs.iter_mut().find(|b| b.sheet == a.sheet);
-            if let Some(found) = found {
-                found.cells.extend(a.cells.clone());
-            } else {
-                merged_cell_diffs.push(a.clone());
-            }
-        });
-        merged_cell_diffs.iter_mut().for_each(|x| {
+        ret.cell_diffs.iter_mut().for_each(|x| {
             x.cells
                 .sort_by(|a, b| a.addr.cmp(&b.addr).then_with(|| a.kind.cmp(&b.kind)));
         });
-        ret.cell_diffs = merged_cell_diffs;
 
         ret
     }
 
+    /// whether some sheets failed to be read
+    pub fn is_partial(&self) -> bool {
+        !self.sheet_errors.is_empty()
+    }
+
     /// get serde-ready diff
     /// #[cfg(feature = "serde")]
     pub fn diff(&mut self) -> Diff {
@@ -96,173 +715,1569 @@ impl Diff {
     }
 
     /// collect sheet diff and cell range diff
-    fn collect_diff(&mut self) {
-        let mut old_workbook: Xlsx<BufReader<File>> = open_workbook(self.old_filepath.as_str())
-            .expect(format!("Cannot open {}", self.old_filepath.as_str()).as_str());
-        let mut new_workbook: Xlsx<BufReader<File>> = open_workbook(self.new_filepath.as_str())
-            .expect(format!("Cannot open {}", self.new_filepath.as_str()).as_str());
+    fn collect_diff(
+        &mut self,
+        old_workbook: &mut Workbook,
+        new_workbook: &mut Workbook,
+        options: &DiffOptions,
+        sheet_filter: &SheetFilter,
+    ) {
+        let old_sheets = old_workbook.sheet_names();
+        let new_sheets = new_workbook.sheet_names();
 
-        let old_sheets = old_workbook.sheet_names().to_owned();
-        let new_sheets = new_workbook.sheet_names().to_owned();
+        // single sheet is compared with the first sheet of the other regardless of names
+        let sheet_pairs: Vec<(String, String)> =
+            if old_workbook.is_single_sheet() || new_workbook.is_single_sheet() {
+                old_sheets
+                    .first()
+                    .zip(new_sheets.first())
+                    .map(|(old, new)| (old.to_owned(), new.to_owned()))
+                    .into_iter()
+                    .collect()
+            } else {
+                let selected = |sheets: Vec<String>| -> Vec<String> {
+                    sheets
+                        .into_iter()
+                        .filter(|x| sheet_filter.is_selected(x))
+                        .collect()
+                };
+                self.collect_sheet_diff(
+                    old_workbook,
+                    new_workbook,
+                    &selected(old_sheets),
+                    &selected(new_sheets),
+                    options,
+                )
+            };
 
-        self.collect_sheet_diff(&old_sheets, &new_sheets);
+        // names are of the whole workbook, which single sheet is not
+        if !old_workbook.is_single_sheet() && !new_workbook.is_single_sheet() {
+            self.collect_defined_name_diff(old_workbook, new_workbook, options, sheet_filter);
+            self.collect_table_diff(old_workbook, new_workbook, options, sheet_filter);
+        }
 
-        let same_name_sheets = filter_same_name_sheets(&old_sheets, &new_sheets);
-        self.collect_cell_value_diff(&mut old_workbook, &mut new_workbook, &same_name_sheets);
-        self.collect_cell_formula_diff(&mut old_workbook, &mut new_workbook, &same_name_sheets);
+        for (old_sheet, new_sheet) in sheet_pairs {
+            self.collect_sheet_cell_diff(
+                old_workbook,
+                new_workbook,
+                &old_sheet,
+                &new_sheet,
+                options,
+            );
+        }
     }
 
-    /// collect sheet diff by name
-    fn collect_sheet_diff(&mut self, old_sheets: &Vec<String>, new_sheets: &Vec<String>) {
-        if *old_sheets == *new_sheets {
-            return;
+    /// collect added / removed defined names and changed references of them.
+    /// names scoped to sheets not selected are left out
+    fn collect_defined_name_diff(
+        &mut self,
+        old_workbook: &mut Workbook,
+        new_workbook: &mut Workbook,
+        options: &DiffOptions,
+        sheet_filter: &SheetFilter,
+    ) {
+        let old_names = old_workbook
+            .defined_names()
+            .map_err(|err| Error::from_package_open(&self.old_filepath, err));
+        let new_names = new_workbook
+            .defined_names()
+            .map_err(|err| Error::from_package_open(&self.new_filepath, err));
+        let (old_names, new_names) = match (old_names, new_names) {
+            (Ok(old_names), Ok(new_names)) => (old_names, new_names),
+            (old_names, new_names) => {
+                [old_names.err(), new_names.err()]
+                    .into_iter()
+                    .flatten()
+                    .for_each(|err| self.push_sheet_error(err));
+                return;
+            }
+        };
+        let is_selected = |x: &DefinedName| {
+            x.scope
+                .as_ref()
+                .map_or(true, |scope| sheet_filter.is_selected(scope))
+        };
+        let is_same_reference = |old: &str, new: &str| {
+            if options.formula != FormulaComparison::default() {
+                normalize_formula(old, None) == normalize_formula(new, None)
+            } else {
+                old == new
+            }
+        };
+        let find = |names: &[DefinedName], x: &DefinedName| {
+            names
+                .iter()
+                .find(|y| y.name == x.name && y.scope == x.scope)
+                .map(|y| y.reference.to_owned())
+        };
+
+        let mut ret: Vec<DefinedNameDiff> = vec![];
+        for old in old_names.iter().filter(|x| is_selected(x)) {
+            let new = find(&new_names, old);
+            let kind = match &new {
+                Some(new) if is_same_reference(&old.reference, new) => continue,
+                Some(_) => DefinedNameDiffKind::Changed,
+                None => DefinedNameDiffKind::Removed,
+            };
+            ret.push(DefinedNameDiff {
+                name: old.name.to_owned(),
+                scope: old.scope.to_owned(),
+                kind,
+                old: Some(old.reference.to_owned()),
+                new,
+            });
         }
+        for new in new_names.iter().filter(|x| is_selected(x)) {
+            if find(&old_names, new).is_none() {
+                ret.push(DefinedNameDiff {
+                    name: new.name.to_owned(),
+                    scope: new.scope.to_owned(),
+                    kind: DefinedNameDiffKind::Added,
+                    old: None,
+                    new: Some(new.reference.to_owned()),
+                });
+            }
+        }
+        ret.sort_by(|a, b| a.scope.cmp(&b.scope).then_with(|| a.name.cmp(&b.name)));
+        self.defined_names = ret;
+    }
 
-        for sheet in old_sheets {
-            if !new_sheets.contains(sheet) {
-                self.sheet_diff.push(SheetDiff {
-                    old: Some(sheet.to_owned()),
-                    new: None,
+    /// collect added / removed tables, and renamed / resized / moved ones and their col
+    /// changes. tables are paired by name, and then by id on the same sheet for renamed ones
+    fn collect_table_diff(
+        &mut self,
+        old_workbook: &mut Workbook,
+        new_workbook: &mut Workbook,
+        options: &DiffOptions,
+        sheet_filter: &SheetFilter,
+    ) {
+        let old_tables = old_workbook
+            .tables()
+            .map_err(|err| Error::from_package_open(&self.old_filepath, err));
+        let new_tables = new_workbook
+            .tables()
+            .map_err(|err| Error::from_package_open(&self.new_filepath, err));
+        let (old_tables, new_tables) = match (old_tables, new_tables) {
+            (Ok(old_tables), Ok(new_tables)) => (old_tables, new_tables),
+            (old_tables, new_tables) => {
+                [old_tables.err(), new_tables.err()]
+                    .into_iter()
+                    .flatten()
+                    .for_each(|err| self.push_sheet_error(err));
+                return;
+            }
+        };
+        let selected = |tables: Vec<Table>| -> Vec<Table> {
+            tables
+                .into_iter()
+                .filter(|x| sheet_filter.is_selected(&x.sheet))
+                .collect()
+        };
+        let (old_tables, new_tables) = (selected(old_tables), selected(new_tables));
+
+        let mut pairs: Vec<(Option<usize>, Option<usize>)> = vec![];
+        let mut is_new_paired = vec![false; new_tables.len()];
+        for (old_index, old) in old_tables.iter().enumerate() {
+            let new_index = new_tables.iter().position(|x| x.name == old.name);
+            if let Some(new_index) = new_index {
+                is_new_paired[new_index] = true;
+            }
+            pairs.push((Some(old_index), new_index));
+        }
+        for (old_index, new_index) in pairs.iter_mut() {
+            let old = &old_tables[old_index.expect("old first")];
+            if new_index.is_none() {
+                *new_index = (0..new_tables.len()).find(|index| {
+                    let new = &new_tables[*index];
+                    !is_new_paired[*index] && new.id == old.id && new.sheet == old.sheet
                 });
+                if let Some(new_index) = new_index {
+                    is_new_paired[*new_index] = true;
+                }
+            }
+        }
+        pairs.extend(
+            (0..new_tables.len())
+                .filter(|x| !is_new_paired[*x])
+                .map(|x| (None, Some(x))),
+        );
+
+        for (old_index, new_index) in pairs {
+            let old = old_index.map(|x| &old_tables[x]);
+            let new = new_index.map(|x| &new_tables[x]);
+            let mut table_diff = TableDiff {
+                kind: TableDiffKind::Changed,
+                old_name: old.map(|x| x.name.to_owned()),
+                new_name: new.map(|x| x.name.to_owned()),
+                old_sheet: old.map(|x| x.sheet.to_owned()),
+                new_sheet: new.map(|x| x.sheet.to_owned()),
+                old_range: old.map(|x| x.range),
+                new_range: new.map(|x| x.range),
+                columns: vec![],
+                records: vec![],
+                duplicate_keys: vec![],
+            };
+            let (old, new) = match (old, new) {
+                (Some(old), Some(new)) => (old, new),
+                (Some(_), None) => {
+                    table_diff.kind = TableDiffKind::Removed;
+                    self.tables.push(table_diff);
+                    continue;
+                }
+                _ => {
+                    table_diff.kind = TableDiffKind::Added;
+                    self.tables.push(table_diff);
+                    continue;
+                }
+            };
+
+            table_diff.columns = Self::collect_table_column_diff(&old.columns, &new.columns);
+            let keys = options
+                .table_keys
+                .get(&old.name)
+                .or_else(|| options.table_keys.get(&new.name));
+            if let Some(keys) = keys {
+                if let Some((records, duplicate_keys)) = self.collect_table_record_diff(
+                    old_workbook,
+                    new_workbook,
+                    old,
+                    new,
+                    keys,
+                    options,
+                ) {
+                    table_diff.records = records;
+                    table_diff.duplicate_keys = duplicate_keys;
+                }
+            }
+
+            let is_changed = table_diff.is_renamed()
+                || table_diff.is_resized()
+                || table_diff.is_moved()
+                || !table_diff.columns.is_empty()
+                || !table_diff.records.is_empty()
+                || !table_diff.duplicate_keys.is_empty();
+            if is_changed {
+                self.tables.push(table_diff);
             }
         }
-        for sheet in new_sheets {
-            if !old_sheets.contains(sheet) {
-                self.sheet_diff.push(SheetDiff {
+    }
+
+    /// collect added / removed cols, and renamed ones keeping id
+    fn collect_table_column_diff(
+        old_columns: &[(String, String)],
+        new_columns: &[(String, String)],
+    ) -> Vec<TableColumnDiff> {
+        let has_name =
+            |columns: &[(String, String)], name: &str| columns.iter().any(|(_, x)| x == name);
+
+        let mut ret: Vec<TableColumnDiff> = vec![];
+        for (old_id, old_name) in old_columns {
+            if has_name(new_columns, old_name) {
+                continue;
+            }
+            let renamed = new_columns
+                .iter()
+                .find(|(id, name)| id == old_id && !has_name(old_columns, name));
+            ret.push(match renamed {
+                Some((_, new_name)) => TableColumnDiff {
+                    kind: TableColumnDiffKind::Renamed,
+                    old: Some(old_name.to_owned()),
+                    new: Some(new_name.to_owned()),
+                },
+                None => TableColumnDiff {
+                    kind: TableColumnDiffKind::Removed,
+                    old: Some(old_name.to_owned()),
+                    new: None,
+                },
+            });
+        }
+        for (_, new_name) in new_columns {
+            let is_paired = has_name(old_columns, new_name)
+                || ret.iter().any(|x| x.new.as_ref() == Some(new_name));
+            if !is_paired {
+                ret.push(TableColumnDiff {
+                    kind: TableColumnDiffKind::Added,
                     old: None,
-                    new: Some(sheet.to_owned()),
+                    new: Some(new_name.to_owned()),
                 });
             }
         }
+        ret
     }
 
-    /// collect value diff in cell range
-    fn collect_cell_value_diff(
+    /// collect diff of table data rows, without header and totals rows, matched by key cols,
+    /// with fields matched by table col name
+    ///
+    /// `None` when either sheet cannot be read or key is missing
+    fn collect_table_record_diff(
         &mut self,
-        old_workbook: &mut Xlsx<BufReader<File>>,
-        new_workbook: &mut Xlsx<BufReader<File>>,
-        same_name_sheets: &Vec<String>,
-    ) {
-        for sheet in same_name_sheets {
-            if let (Ok(old_range), Ok(new_range)) = (
-                old_workbook.worksheet_range(sheet),
-                new_workbook.worksheet_range(sheet),
-            ) {
-                let mut cell_diffs: Vec<CellDiff> = vec![];
-
-                let (start_row, start_col, end_row, end_col) = diff_range(
-                    old_range.start(),
-                    new_range.start(),
-                    old_range.end(),
-                    new_range.end(),
-                );
-
-                for row in start_row..end_row {
-                    for col in start_col..end_col {
-                        let old_cell = old_range.get_value((row, col)).unwrap_or(&Data::Empty);
-                        let new_cell = new_range.get_value((row, col)).unwrap_or(&Data::Empty);
-
-                        if old_cell != new_cell {
-                            let row = (row + 1) as usize;
-                            let col = (col + 1) as usize;
-                            cell_diffs.push(CellDiff {
-                                row,
-                                col,
-                                addr: cell_pos_to_address(row, col),
-                                kind: CellDiffKind::Value,
-                                old: if old_cell != &Data::Empty {
-                                    Some(old_cell.to_string())
-                                } else {
-                                    None
-                                },
-                                new: if new_cell != &Data::Empty {
-                                    Some(new_cell.to_string())
-                                } else {
-                                    None
-                                },
-                            });
-                        }
+        old_workbook: &mut Workbook,
+        new_workbook: &mut Workbook,
+        old: &Table,
+        new: &Table,
+        keys: &[String],
+        options: &DiffOptions,
+    ) -> Option<(Vec<RecordDiff>, Vec<DuplicateKey>)> {
+        let keys: Vec<KeyColumn> = keys
+            .iter()
+            .map(|x| KeyColumn::Header(x.to_owned()))
+            .collect();
+        let old_range = old_workbook
+            .worksheet_range(&old.sheet)
+            .map_err(|err| Error::from_sheet_read(&self.old_filepath, &old.sheet, err));
+        let new_range = new_workbook
+            .worksheet_range(&new.sheet)
+            .map_err(|err| Error::from_sheet_read(&self.new_filepath, &new.sheet, err));
+        // header row is left out since fields are named by table cols
+        let data_range = |range: &Range<Data>, table: &Table| {
+            table
+                .data_range()
+                .and_then(|data_range| crop_range(range, &[data_range]).ok())
+                .unwrap_or_else(Range::empty)
+        };
+        let (old_range, new_range) = match (old_range, new_range) {
+            (Ok(old_range), Ok(new_range)) => {
+                (data_range(&old_range, old), data_range(&new_range, new))
+            }
+            (old_range, new_range) => {
+                [old_range.err(), new_range.err()]
+                    .into_iter()
+                    .flatten()
+                    .for_each(|err| self.push_sheet_error(err));
+                return None;
+            }
+        };
+
+        let old_records =
+            Records::read_with_fields(&old_range, old.fields(), &keys).map_err(|key| {
+                Error::KeyNotFound {
+                    filepath: self.old_filepath.to_owned(),
+                    sheet: old.sheet.to_owned(),
+                    key: key.to_string(),
+                }
+            });
+        let new_records =
+            Records::read_with_fields(&new_range, new.fields(), &keys).map_err(|key| {
+                Error::KeyNotFound {
+                    filepath: self.new_filepath.to_owned(),
+                    sheet: new.sheet.to_owned(),
+                    key: key.to_string(),
+                }
+            });
+        match (old_records, new_records) {
+            (Ok(old_records), Ok(new_records)) => Some(Self::collect_record_diff(
+                &old_range,
+                &new_range,
+                &old_records,
+                &new_records,
+                &old.sheet,
+                options,
+            )),
+            (old_records, new_records) => {
+                [old_records.err(), new_records.err()]
+                    .into_iter()
+                    .flatten()
+                    .for_each(|err| self.push_sheet_error(err));
+                None
+            }
+        }
+    }
+
+    /// collect sheet diff by name, and by content for renamed sheets when enabled,
+    /// and then moves and visibility changes of sheets in both
+    ///
+    /// return: pairs of sheets in both, including renamed ones
+    fn collect_sheet_diff(
+        &mut self,
+        old_workbook: &mut Workbook,
+        new_workbook: &mut Workbook,
+        old_sheets: &[String],
+        new_sheets: &[String],
+        options: &DiffOptions,
+    ) -> Vec<(String, String)> {
+        let removed: Vec<&String> = old_sheets
+            .iter()
+            .filter(|x| !new_sheets.contains(x))
+            .collect();
+        let added: Vec<&String> = new_sheets
+            .iter()
+            .filter(|x| !old_sheets.contains(x))
+            .collect();
+        let renamed = match options.sheet_rename_similarity {
+            Some(threshold) => {
+                Self::pair_renamed_sheets(old_workbook, new_workbook, &removed, &added, threshold)
+            }
+            None => vec![],
+        };
+
+        for sheet in removed {
+            match renamed.iter().find(|(old, _, _)| old == sheet) {
+                Some((old, new, similarity)) => self.sheet_diff.push(SheetDiff {
+                    similarity: Some(*similarity),
+                    ..SheetDiff::new(Some(old), Some(new))
+                }),
+                None => self.sheet_diff.push(SheetDiff::new(Some(sheet), None)),
+            }
+        }
+        for sheet in added {
+            if !renamed.iter().any(|(_, new, _)| new == sheet) {
+                self.sheet_diff.push(SheetDiff::new(None, Some(sheet)));
+            }
+        }
+
+        let pairs: Vec<(String, String)> = filter_same_name_sheets(old_sheets, new_sheets)
+            .into_iter()
+            .map(|x| (x.clone(), x))
+            .chain(renamed.into_iter().map(|(old, new, _)| (old, new)))
+            .collect();
+
+        // tab positions in whole workbook
+        let position = |workbook: &Workbook, sheet: &str| {
+            workbook.sheet_position(sheet).unwrap_or_default() as u32
+        };
+        let moved = moved_pairs(
+            pairs
+                .iter()
+                .map(|(old, new)| (position(old_workbook, old), position(new_workbook, new)))
+                .collect(),
+        );
+        for (old_sheet, new_sheet) in pairs.iter() {
+            let old_position = position(old_workbook, old_sheet);
+            let new_position = position(new_workbook, new_sheet);
+            let is_moved = moved.contains(&(old_position, new_position));
+            let old_visibility = old_workbook.sheet_visibility(old_sheet);
+            let new_visibility = new_workbook.sheet_visibility(new_sheet);
+            if !is_moved && old_visibility == new_visibility {
+                continue;
+            }
+
+            // renamed sheet has its entry already
+            let index = self.sheet_diff.iter().position(|x| {
+                x.old.as_ref() == Some(old_sheet) && x.new.as_ref() == Some(new_sheet)
+            });
+            let sheet_diff = match index {
+                Some(index) => &mut self.sheet_diff[index],
+                None => {
+                    self.sheet_diff
+                        .push(SheetDiff::new(Some(old_sheet), Some(new_sheet)));
+                    self.sheet_diff.last_mut().expect("pushed")
+                }
+            };
+            if is_moved {
+                sheet_diff.old_position = Some((old_position + 1) as usize);
+                sheet_diff.new_position = Some((new_position + 1) as usize);
+            }
+            if old_visibility != new_visibility {
+                sheet_diff.old_visibility = Some(old_visibility);
+                sheet_diff.new_visibility = Some(new_visibility);
+            }
+        }
+
+        pairs
+    }
+
+    /// pair removed and added sheets whose cell values are similar enough, most similar first
+    ///
+    /// sheets failed to be read are left unpaired
+    fn pair_renamed_sheets(
+        old_workbook: &mut Workbook,
+        new_workbook: &mut Workbook,
+        removed: &[&String],
+        added: &[&String],
+        threshold: f64,
+    ) -> Vec<(String, String, f64)> {
+        if removed.is_empty() || added.is_empty() {
+            return vec![];
+        }
+
+        let sheet_values = |workbook: &mut Workbook, sheet: &str| {
+            workbook.worksheet_range(sheet).ok().map(|range| {
+                range
+                    .used_cells()
+                    .map(|(_, _, x)| data_to_string(x))
+                    .collect::<Vec<String>>()
+            })
+        };
+        let old_values: Vec<Option<Vec<String>>> = removed
+            .iter()
+            .map(|x| sheet_values(old_workbook, x))
+            .collect();
+        let new_values: Vec<Option<Vec<String>>> = added
+            .iter()
+            .map(|x| sheet_values(new_workbook, x))
+            .collect();
+
+        let mut candidates: Vec<(f64, usize, usize)> = vec![];
+        for (old_index, old_values) in old_values.iter().enumerate() {
+            for (new_index, new_values) in new_values.iter().enumerate() {
+                if let (Some(old_values), Some(new_values)) = (old_values, new_values) {
+                    let similarity = values_similarity(old_values, new_values);
+                    if 0.0 < similarity && threshold <= similarity {
+                        candidates.push((similarity, old_index, new_index));
                     }
                 }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
 
-                if !cell_diffs.is_empty() {
-                    let sheet_cell_diff = SheetCellDiff {
-                        sheet: sheet.to_owned(),
-                        cells: cell_diffs,
-                    };
-                    self.cell_diffs.push(sheet_cell_diff);
+        let mut ret: Vec<(String, String, f64)> = vec![];
+        let mut is_old_paired = vec![false; removed.len()];
+        let mut is_new_paired = vec![false; added.len()];
+        for (similarity, old_index, new_index) in candidates {
+            if !is_old_paired[old_index] && !is_new_paired[new_index] {
+                is_old_paired[old_index] = true;
+                is_new_paired[new_index] = true;
+                ret.push((
+                    removed[old_index].to_owned(),
+                    added[new_index].to_owned(),
+                    similarity,
+                ));
+            }
+        }
+        ret
+    }
+
+    /// collect row, value and formula diff in sheet
+    fn collect_sheet_cell_diff(
+        &mut self,
+        old_workbook: &mut Workbook,
+        new_workbook: &mut Workbook,
+        old_sheet: &str,
+        new_sheet: &str,
+        options: &DiffOptions,
+    ) {
+        let old_range = old_workbook
+            .worksheet_range(old_sheet)
+            .map_err(|err| Error::from_sheet_read(&self.old_filepath, old_sheet, err));
+        let new_range = new_workbook
+            .worksheet_range(new_sheet)
+            .map_err(|err| Error::from_sheet_read(&self.new_filepath, new_sheet, err));
+        let old_formula = old_workbook
+            .worksheet_formula(old_sheet)
+            .map_err(|err| Error::from_sheet_read(&self.old_filepath, old_sheet, err));
+        let new_formula = new_workbook
+            .worksheet_formula(new_sheet)
+            .map_err(|err| Error::from_sheet_read(&self.new_filepath, new_sheet, err));
+
+        let (old_range, new_range, old_formula, new_formula) =
+            match (old_range, new_range, old_formula, new_formula) {
+                (Ok(old_range), Ok(new_range), Ok(old_formula), Ok(new_formula)) => {
+                    (old_range, new_range, old_formula, new_formula)
                 }
-            } else {
-                println!("Failed to read sheet: {}", sheet);
+                (old_range, new_range, old_formula, new_formula) => {
+                    [
+                        old_range.err(),
+                        new_range.err(),
+                        old_formula.err(),
+                        new_formula.err(),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .for_each(|err| self.push_sheet_error(err));
+                    return;
+                }
+            };
+
+        let cell_ranges = options
+            .sheets
+            .ranges
+            .get(old_sheet)
+            .or_else(|| options.sheets.ranges.get(new_sheet));
+        let cropped = cell_ranges
+            .map(|cell_ranges| -> Result<_, CellRange> {
+                Ok((
+                    crop_range(&old_range, cell_ranges)?,
+                    crop_range(&new_range, cell_ranges)?,
+                    crop_range(&old_formula, cell_ranges)?,
+                    crop_range(&new_formula, cell_ranges)?,
+                ))
+            })
+            .transpose();
+        let (old_range, new_range, old_formula, new_formula) = match cropped {
+            Ok(Some(cropped)) => cropped,
+            Ok(None) => (old_range, new_range, old_formula, new_formula),
+            Err(range) => {
+                self.push_sheet_error(Error::InvalidCellRange {
+                    sheet: old_sheet.to_owned(),
+                    range: range.to_string(),
+                });
+                return;
+            }
+        };
+
+        let old_merged = old_workbook
+            .merged_regions(old_sheet)
+            .map_err(|err| Error::from_sheet_read(&self.old_filepath, old_sheet, err));
+        let new_merged = new_workbook
+            .merged_regions(new_sheet)
+            .map_err(|err| Error::from_sheet_read(&self.new_filepath, new_sheet, err));
+        let mut merged = |merged: Result<Vec<CellRange>, Error>| match merged {
+            Ok(merged) => merged
+                .into_iter()
+                .filter(|x| {
+                    cell_ranges.map_or(true, |cell_ranges| {
+                        cell_ranges
+                            .iter()
+                            .any(|range| range.contains(x.start_row, x.start_col))
+                    })
+                })
+                .collect(),
+            Err(err) => {
+                self.push_sheet_error(err);
+                vec![]
+            }
+        };
+        let (old_merged, new_merged) = (merged(old_merged), merged(new_merged));
+        let merged_cells = Self::collect_merged_cell_diff(&old_merged, &new_merged);
+
+        let old_comments = old_workbook
+            .comments(old_sheet)
+            .map_err(|err| Error::from_sheet_read(&self.old_filepath, old_sheet, err));
+        let new_comments = new_workbook
+            .comments(new_sheet)
+            .map_err(|err| Error::from_sheet_read(&self.new_filepath, new_sheet, err));
+        let is_in_cell_ranges = |(row, col): &(u32, u32)| {
+            cell_ranges.map_or(true, |cell_ranges| {
+                cell_ranges
+                    .iter()
+                    .any(|range| range.contains(*row as usize + 1, *col as usize + 1))
+            })
+        };
+        let mut comments = |comments: Result<HashMap<(u32, u32), String>, Error>| match comments {
+            Ok(comments) => comments
+                .into_iter()
+                .filter(|(pos, _)| is_in_cell_ranges(pos))
+                .collect(),
+            Err(err) => {
+                self.push_sheet_error(err);
+                HashMap::new()
+            }
+        };
+        let (old_comments, new_comments) = (comments(old_comments), comments(new_comments));
+
+        let (old_styles, new_styles) = if options.compare_styles {
+            let old_styles = old_workbook
+                .cell_styles(old_sheet)
+                .map_err(|err| Error::from_sheet_read(&self.old_filepath, old_sheet, err));
+            let new_styles = new_workbook
+                .cell_styles(new_sheet)
+                .map_err(|err| Error::from_sheet_read(&self.new_filepath, new_sheet, err));
+            let mut styles = |styles: Result<SheetStyles, Error>| match styles {
+                Ok(mut styles) => {
+                    styles.cells.retain(|pos, _| is_in_cell_ranges(pos));
+                    styles
+                }
+                Err(err) => {
+                    self.push_sheet_error(err);
+                    SheetStyles::default()
+                }
+            };
+            (styles(old_styles), styles(new_styles))
+        } else {
+            (SheetStyles::default(), SheetStyles::default())
+        };
+
+        let keys = options
+            .keys
+            .get(old_sheet)
+            .or_else(|| options.keys.get(new_sheet));
+        if let Some(keys) = keys {
+            self.collect_sheet_record_diff(
+                &old_range, &new_range, old_sheet, new_sheet, keys, options,
+            );
+            // records have no cell alignment, so comments and styles are compared at the same
+            // position
+            let counterparts = Alignment {
+                rows: vec![],
+                cols: vec![],
+            }
+            .counterparts();
+            let mut cell_diffs =
+                Self::collect_comment_diff(&old_comments, &new_comments, &counterparts);
+            cell_diffs.extend(Self::collect_style_diff(
+                &old_styles,
+                &new_styles,
+                &counterparts,
+            ));
+            self.attach_sheet_cell_diff(old_sheet, new_sheet, cell_diffs, merged_cells);
+            return;
+        }
+
+        let (start_row, start_col, end_row, end_col) = union_range(&[
+            diff_range(
+                old_range.start(),
+                new_range.start(),
+                old_range.end(),
+                new_range.end(),
+            ),
+            diff_range(
+                old_formula.start(),
+                new_formula.start(),
+                old_formula.end(),
+                new_formula.end(),
+            ),
+        ]);
+
+        // cols first so that rows are compared without cols inserted / deleted
+        let cols = match options.align_columns {
+            Some(by) => {
+                let all_cols: Vec<u32> = (start_col..end_col).collect();
+                let old_cols = transpose(range_lines(&old_range, start_row..end_row, &all_cols));
+                let new_cols = transpose(range_lines(&new_range, start_row..end_row, &all_cols));
+                align_columns(&old_cols, &new_cols, by, start_col)
+            }
+            None => positional_pairs(start_col, end_col),
+        };
+        let rows = if options.align_rows {
+            let (old_cols, new_cols): (Vec<u32>, Vec<u32>) = matched_pairs(&cols).unzip();
+            // rows below the end of either sheet are not there to be inserted / deleted
+            let end_row_of = |range: &Range<Data>, formula: &Range<String>| {
+                let (_, _, side_end_row, _) =
+                    diff_range(range.start(), formula.start(), range.end(), formula.end());
+                side_end_row.min(end_row)
+            };
+            let old_end_row = end_row_of(&old_range, &old_formula);
+            let new_end_row = end_row_of(&new_range, &new_formula);
+            let old_lines = range_lines(&old_range, start_row..old_end_row, &old_cols);
+            let new_lines = range_lines(&new_range, start_row..new_end_row, &new_cols);
+            align_lines(&old_lines, &new_lines, start_row)
+        } else {
+            positional_pairs(start_row, end_row)
+        };
+        let alignment = Alignment { rows, cols };
+
+        let column_diffs = Self::collect_column_diff(&old_range, &new_range, &alignment, start_row);
+        let row_diffs = Self::collect_row_diff(&old_range, &new_range, &alignment);
+        let mut cell_diffs =
+            Self::collect_cell_value_diff(&old_range, &new_range, &alignment, old_sheet, options);
+        cell_diffs.extend(Self::collect_cell_formula_diff(
+            &old_formula,
+            &new_formula,
+            &alignment,
+            options,
+        ));
+        Self::classify_result_diff(&mut cell_diffs, &old_formula, &new_formula);
+        Self::classify_constant_diff(&mut cell_diffs, &old_range, &new_range);
+        let counterparts = alignment.counterparts();
+        cell_diffs.extend(Self::collect_comment_diff(
+            &old_comments,
+            &new_comments,
+            &counterparts,
+        ));
+        cell_diffs.extend(Self::collect_style_diff(
+            &old_styles,
+            &new_styles,
+            &counterparts,
+        ));
+
+        if !column_diffs.is_empty() || !row_diffs.is_empty() || !cell_diffs.is_empty() {
+            let sheet_cell_diff = SheetCellDiff {
+                sheet: old_sheet.to_owned(),
+                new_sheet: (old_sheet != new_sheet).then(|| new_sheet.to_owned()),
+                columns: column_diffs,
+                rows: row_diffs,
+                cells: cell_diffs,
+                records: vec![],
+                duplicate_keys: vec![],
+                merged_cells: vec![],
+            };
+            self.cell_diffs.push(sheet_cell_diff);
+        }
+        self.attach_sheet_cell_diff(old_sheet, new_sheet, vec![], merged_cells);
+    }
+
+    /// collect comment diff. comments move along with rows / cols aligned, and those out of
+    /// alignment stay at the same position
+    fn collect_comment_diff(
+        old_comments: &HashMap<(u32, u32), String>,
+        new_comments: &HashMap<(u32, u32), String>,
+        counterparts: &Counterparts,
+    ) -> Vec<CellDiff> {
+        let mut ret: Vec<CellDiff> = vec![];
+        for (old_pos, old_comment) in old_comments.iter() {
+            let new_pos = counterparts.pos(*old_pos, true);
+            let new_comment = new_pos.and_then(|x| new_comments.get(&x));
+            if new_comment != Some(old_comment) {
+                ret.push(CellDiff::new(
+                    *old_pos,
+                    new_pos.unwrap_or(*old_pos),
+                    CellDiffKind::Comment,
+                    Some(old_comment.to_owned()),
+                    new_comment.cloned(),
+                ));
             }
         }
+        for (new_pos, new_comment) in new_comments.iter() {
+            let old_pos = counterparts.pos(*new_pos, false);
+            if !old_pos.is_some_and(|x| old_comments.contains_key(&x)) {
+                ret.push(CellDiff::new(
+                    old_pos.unwrap_or(*new_pos),
+                    *new_pos,
+                    CellDiffKind::Comment,
+                    None,
+                    Some(new_comment.to_owned()),
+                ));
+            }
+        }
+        ret
     }
 
-    /// collect formula diff in cell range
-    fn collect_cell_formula_diff(
+    /// collect style diff of cells in both sheets, moving along with rows / cols aligned as
+    /// comments do. texts list changed properties only such as `bold: false; fill: none`
+    fn collect_style_diff(
+        old_styles: &SheetStyles,
+        new_styles: &SheetStyles,
+        counterparts: &Counterparts,
+    ) -> Vec<CellDiff> {
+        let style_diff = |old_pos: (u32, u32), new_pos: (u32, u32)| {
+            let old_style = old_styles
+                .cells
+                .get(&old_pos)
+                .unwrap_or(&old_styles.default);
+            let new_style = new_styles
+                .cells
+                .get(&new_pos)
+                .unwrap_or(&new_styles.default);
+            let (old, new) = style_diff_texts(old_style, new_style)?;
+            Some(CellDiff::new(
+                old_pos,
+                new_pos,
+                CellDiffKind::Style,
+                Some(old),
+                Some(new),
+            ))
+        };
+
+        let mut ret: Vec<CellDiff> = vec![];
+        for old_pos in old_styles.cells.keys() {
+            if let Some(new_pos) = counterparts.pos(*old_pos, true) {
+                ret.extend(style_diff(*old_pos, new_pos));
+            }
+        }
+        for new_pos in new_styles.cells.keys() {
+            match counterparts.pos(*new_pos, false) {
+                Some(old_pos) if !old_styles.cells.contains_key(&old_pos) => {
+                    ret.extend(style_diff(old_pos, *new_pos));
+                }
+                _ => (),
+            }
+        }
+        ret
+    }
+
+    /// collect added / removed merged cell ranges, and resized ones sharing top-left cell
+    fn collect_merged_cell_diff(old: &[CellRange], new: &[CellRange]) -> Vec<MergedCellDiff> {
+        let removed: Vec<&CellRange> = old.iter().filter(|x| !new.contains(x)).collect();
+        let added: Vec<&CellRange> = new.iter().filter(|x| !old.contains(x)).collect();
+        let is_same_start =
+            |a: &CellRange, b: &CellRange| a.start_row == b.start_row && a.start_col == b.start_col;
+
+        let mut ret: Vec<MergedCellDiff> = vec![];
+        for old in removed.iter() {
+            match added.iter().find(|new| is_same_start(old, new)) {
+                Some(new) => ret.push(MergedCellDiff {
+                    kind: MergedCellDiffKind::Resized,
+                    old: Some(**old),
+                    new: Some(**new),
+                }),
+                None => ret.push(MergedCellDiff {
+                    kind: MergedCellDiffKind::Removed,
+                    old: Some(**old),
+                    new: None,
+                }),
+            }
+        }
+        for new in added {
+            if !removed.iter().any(|old| is_same_start(old, new)) {
+                ret.push(MergedCellDiff {
+                    kind: MergedCellDiffKind::Added,
+                    old: None,
+                    new: Some(*new),
+                });
+            }
+        }
+        ret.sort_by_key(|x| {
+            let range = x.old.or(x.new).expect("either exists");
+            (range.start_row, range.start_col)
+        });
+        ret
+    }
+
+    /// attach cell and merged cell diffs to sheet cell diff, which is added when missing
+    fn attach_sheet_cell_diff(
         &mut self,
-        old_workbook: &mut Xlsx<BufReader<File>>,
-        new_workbook: &mut Xlsx<BufReader<File>>,
-        same_name_sheets: &Vec<String>,
+        old_sheet: &str,
+        new_sheet: &str,
+        cells: Vec<CellDiff>,
+        merged_cells: Vec<MergedCellDiff>,
     ) {
-        for sheet in same_name_sheets {
-            if let (Ok(old_range), Ok(new_range)) = (
-                old_workbook.worksheet_formula(sheet),
-                new_workbook.worksheet_formula(sheet),
-            ) {
-                let mut cell_diffs: Vec<CellDiff> = vec![];
-
-                let (start_row, start_col, end_row, end_col) = diff_range(
-                    old_range.start(),
-                    new_range.start(),
-                    old_range.end(),
-                    new_range.end(),
-                );
-
-                for row in start_row..end_row {
-                    for col in start_col..end_col {
-                        let old_cell = match old_range.get_value((row, col)) {
-                            Some(x) => &Data::String(x.to_string()),
-                            None => &Data::Empty,
-                        };
-                        let new_cell = match new_range.get_value((row, col)) {
-                            Some(x) => &Data::String(x.to_string()),
-                            None => &Data::Empty,
-                        };
+        if cells.is_empty() && merged_cells.is_empty() {
+            return;
+        }
+        let new_sheet = (old_sheet != new_sheet).then(|| new_sheet.to_owned());
+        match self
+            .cell_diffs
+            .iter_mut()
+            .find(|x| x.sheet == old_sheet && x.new_sheet == new_sheet)
+        {
+            Some(sheet_cell_diff) => {
+                sheet_cell_diff.cells.extend(cells);
+                sheet_cell_diff.merged_cells = merged_cells;
+            }
+            None => self.cell_diffs.push(SheetCellDiff {
+                sheet: old_sheet.to_owned(),
+                new_sheet,
+                columns: vec![],
+                rows: vec![],
+                cells,
+                records: vec![],
+                duplicate_keys: vec![],
+                merged_cells,
+            }),
+        }
+    }
+
+    /// collect diff of records matched by key cols
+    fn collect_sheet_record_diff(
+        &mut self,
+        old_range: &Range<Data>,
+        new_range: &Range<Data>,
+        old_sheet: &str,
+        new_sheet: &str,
+        keys: &[KeyColumn],
+        options: &DiffOptions,
+    ) {
+        let old_records = Records::read(old_range, keys).map_err(|key| Error::KeyNotFound {
+            filepath: self.old_filepath.to_owned(),
+            sheet: old_sheet.to_owned(),
+            key: key.to_string(),
+        });
+        let new_records = Records::read(new_range, keys).map_err(|key| Error::KeyNotFound {
+            filepath: self.new_filepath.to_owned(),
+            sheet: new_sheet.to_owned(),
+            key: key.to_string(),
+        });
+        let (old_records, new_records) = match (old_records, new_records) {
+            (Ok(old_records), Ok(new_records)) => (old_records, new_records),
+            (old_records, new_records) => {
+                [old_records.err(), new_records.err()]
+                    .into_iter()
+                    .flatten()
+                    .for_each(|err| self.push_sheet_error(err));
+                return;
+            }
+        };
+
+        let (records, duplicate_keys) = Self::collect_record_diff(
+            old_range,
+            new_range,
+            &old_records,
+            &new_records,
+            old_sheet,
+            options,
+        );
+        if !records.is_empty() || !duplicate_keys.is_empty() {
+            self.cell_diffs.push(SheetCellDiff {
+                sheet: old_sheet.to_owned(),
+                new_sheet: (old_sheet != new_sheet).then(|| new_sheet.to_owned()),
+                columns: vec![],
+                rows: vec![],
+                cells: vec![],
+                records,
+                duplicate_keys,
+                merged_cells: vec![],
+            });
+        }
+    }
+
+    /// collect added / removed / changed records, and keys shared by several records
+    fn collect_record_diff(
+        old_range: &Range<Data>,
+        new_range: &Range<Data>,
+        old_records: &Records,
+        new_records: &Records,
+        sheet: &str,
+        options: &DiffOptions,
+    ) -> (Vec<RecordDiff>, Vec<DuplicateKey>) {
+        let old_rows = old_records.rows_by_key();
+        let new_rows = new_records.rows_by_key();
+        let to_rows = |rows: Option<&Vec<u32>>| -> Vec<usize> {
+            rows.map(|rows| rows.iter().map(|x| (x + 1) as usize).collect())
+                .unwrap_or_default()
+        };
+
+        // duplicate keys in order of appearance, old first
+        let mut duplicate_keys: Vec<DuplicateKey> = vec![];
+        let mut duplicated: HashSet<&str> = HashSet::new();
+        for (key, _) in old_records.keyed.iter().chain(new_records.keyed.iter()) {
+            let key = key.as_str();
+            let is_duplicate = old_rows.get(key).is_some_and(|x| 1 < x.len())
+                || new_rows.get(key).is_some_and(|x| 1 < x.len());
+            if is_duplicate && duplicated.insert(key) {
+                duplicate_keys.push(DuplicateKey {
+                    key: key.to_owned(),
+                    old_rows: to_rows(old_rows.get(key)),
+                    new_rows: to_rows(new_rows.get(key)),
+                });
+            }
+        }
+
+        // fields compared are those in both, matched by name
+        let common_fields: Vec<(&str, u32, u32)> = old_records
+            .fields
+            .iter()
+            .filter_map(|(old_col, field)| {
+                new_records
+                    .fields
+                    .iter()
+                    .find(|(_, x)| x == field)
+                    .map(|(new_col, _)| (field.as_str(), *old_col, *new_col))
+            })
+            .collect();
+        let record_text = |range: &Range<Data>, records: &Records, row: u32| {
+            records
+                .fields
+                .iter()
+                .map(|(col, _)| cell_text(range, row, *col))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let non_empty = |text: String| (!text.is_empty()).then_some(text);
+
+        let tolerances: Vec<NumericTolerance> = common_fields
+            .iter()
+            .map(|(_, old_col, _)| options.tolerance_for(sheet, (old_col + 1) as usize))
+            .collect();
 
-                        if old_cell != new_cell {
-                            let row = (row + 1) as usize;
-                            let col = (col + 1) as usize;
-                            cell_diffs.push(CellDiff {
-                                row,
-                                col,
-                                addr: cell_pos_to_address(row, col),
-                                kind: CellDiffKind::Formula,
-                                old: if old_cell != &Data::Empty {
-                                    Some(old_cell.to_string())
-                                } else {
-                                    None
-                                },
-                                new: if new_cell != &Data::Empty {
-                                    Some(new_cell.to_string())
-                                } else {
-                                    None
-                                },
-                            });
-                        }
+        let mut records: Vec<RecordDiff> = vec![];
+        for (key, old_row) in old_records.keyed.iter() {
+            if duplicated.contains(key.as_str()) {
+                continue;
+            }
+            match new_rows.get(key.as_str()) {
+                Some(new_row) => {
+                    let new_row = new_row[0];
+                    let fields: Vec<FieldDiff> = common_fields
+                        .iter()
+                        .zip(tolerances.iter())
+                        .filter_map(|((field, old_col, new_col), tolerance)| {
+                            let old_cell = old_range.get_value((*old_row, *old_col));
+                            let new_cell = new_range.get_value((new_row, *new_col));
+                            let changed = compare_data(
+                                old_cell.unwrap_or(&Data::Empty),
+                                new_cell.unwrap_or(&Data::Empty),
+                                tolerance,
+                                options,
+                            );
+                            let old = cell_text(old_range, *old_row, *old_col);
+                            let new = cell_text(new_range, new_row, *new_col);
+                            changed.map(|_| FieldDiff {
+                                field: field.to_string(),
+                                old: non_empty(old),
+                                new: non_empty(new),
+                            })
+                        })
+                        .collect();
+                    if !fields.is_empty() {
+                        records.push(RecordDiff {
+                            kind: RecordDiffKind::Changed,
+                            key: key.to_owned(),
+                            old_row: Some((old_row + 1) as usize),
+                            new_row: Some((new_row + 1) as usize),
+                            old: None,
+                            new: None,
+                            fields,
+                        });
                     }
                 }
+                None => records.push(RecordDiff {
+                    kind: RecordDiffKind::Removed,
+                    key: key.to_owned(),
+                    old_row: Some((old_row + 1) as usize),
+                    new_row: None,
+                    old: Some(record_text(old_range, old_records, *old_row)),
+                    new: None,
+                    fields: vec![],
+                }),
+            }
+        }
+        for (key, new_row) in new_records.keyed.iter() {
+            if duplicated.contains(key.as_str()) || old_rows.contains_key(key.as_str()) {
+                continue;
+            }
+            records.push(RecordDiff {
+                kind: RecordDiffKind::Added,
+                key: key.to_owned(),
+                old_row: None,
+                new_row: Some((new_row + 1) as usize),
+                old: None,
+                new: Some(record_text(new_range, new_records, *new_row)),
+                fields: vec![],
+            });
+        }
+
+        // rows without key cannot be matched by key, so those with the same text are regarded
+        // as the same and the others as removed / added
+        let mut new_unkeyed: Vec<(u32, String)> = new_records
+            .unkeyed
+            .iter()
+            .map(|row| (*row, record_text(new_range, new_records, *row)))
+            .collect();
+        for old_row in old_records.unkeyed.iter() {
+            let old = record_text(old_range, old_records, *old_row);
+            match new_unkeyed.iter().position(|(_, new)| *new == old) {
+                Some(index) => {
+                    new_unkeyed.remove(index);
+                }
+                None => records.push(RecordDiff {
+                    kind: RecordDiffKind::Removed,
+                    key: String::new(),
+                    old_row: Some((old_row + 1) as usize),
+                    new_row: None,
+                    old: Some(old),
+                    new: None,
+                    fields: vec![],
+                }),
+            }
+        }
+        records.extend(new_unkeyed.into_iter().map(|(new_row, new)| RecordDiff {
+            kind: RecordDiffKind::Added,
+            key: String::new(),
+            old_row: None,
+            new_row: Some((new_row + 1) as usize),
+            old: None,
+            new: Some(new),
+            fields: vec![],
+        }));
+
+        (records, duplicate_keys)
+    }
+
+    /// collect inserted / deleted / moved cols
+    fn collect_column_diff(
+        old_range: &Range<Data>,
+        new_range: &Range<Data>,
+        alignment: &Alignment,
+        header_row: u32,
+    ) -> Vec<ColumnDiff> {
+        let header = |range: &Range<Data>, col: u32| {
+            range
+                .get_value((header_row, col))
+                .filter(|x| *x != &Data::Empty)
+                .map(data_to_string)
+        };
+
+        let mut ret: Vec<ColumnDiff> = alignment
+            .cols
+            .iter()
+            .filter_map(|pair| match *pair {
+                (Some(old_col), None) => Some(ColumnDiff {
+                    kind: ColumnDiffKind::Deleted,
+                    old_col: Some((old_col + 1) as usize),
+                    new_col: None,
+                    old: header(old_range, old_col),
+                    new: None,
+                }),
+                (None, Some(new_col)) => Some(ColumnDiff {
+                    kind: ColumnDiffKind::Inserted,
+                    old_col: None,
+                    new_col: Some((new_col + 1) as usize),
+                    old: None,
+                    new: header(new_range, new_col),
+                }),
+                _ => None,
+            })
+            .collect();
+        ret.extend(
+            alignment
+                .moved_cols()
+                .into_iter()
+                .map(|(old_col, new_col)| ColumnDiff {
+                    kind: ColumnDiffKind::Moved,
+                    old_col: Some((old_col + 1) as usize),
+                    new_col: Some((new_col + 1) as usize),
+                    old: header(old_range, old_col),
+                    new: header(new_range, new_col),
+                }),
+        );
+        ret.sort_by_key(|x| (x.old_col.or(x.new_col), x.new_col));
+
+        ret
+    }
+
+    /// collect inserted / deleted rows
+    fn collect_row_diff(
+        old_range: &Range<Data>,
+        new_range: &Range<Data>,
+        alignment: &Alignment,
+    ) -> Vec<RowDiff> {
+        let old_cols: Vec<u32> = alignment.cols.iter().flat_map(|(old, _)| *old).collect();
+        let new_cols: Vec<u32> = alignment.cols.iter().flat_map(|(_, new)| *new).collect();
+        let row_text = |range: &Range<Data>, row: u32, cols: &[u32]| {
+            let texts: Vec<String> = cols
+                .iter()
+                .map(|col| {
+                    range
+                        .get_value((row, *col))
+                        .map(data_to_string)
+                        .unwrap_or_default()
+                })
+                .collect();
+            let len = texts
+                .iter()
+                .rposition(|x| !x.is_empty())
+                .map_or(0, |x| x + 1);
+            texts[..len].join(", ")
+        };
+
+        alignment
+            .rows
+            .iter()
+            .filter_map(|pair| match *pair {
+                (Some(old_row), None) => Some(RowDiff {
+                    old_row: Some((old_row + 1) as usize),
+                    new_row: None,
+                    old: Some(row_text(old_range, old_row, &old_cols)),
+                    new: None,
+                }),
+                (None, Some(new_row)) => Some(RowDiff {
+                    old_row: None,
+                    new_row: Some((new_row + 1) as usize),
+                    old: None,
+                    new: Some(row_text(new_range, new_row, &new_cols)),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// collect value diff in cell range. numbers within tolerance are regarded as equal
+    fn collect_cell_value_diff(
+        old_range: &Range<Data>,
+        new_range: &Range<Data>,
+        alignment: &Alignment,
+        sheet: &str,
+        options: &DiffOptions,
+    ) -> Vec<CellDiff> {
+        let mut cell_diffs: Vec<CellDiff> = vec![];
+
+        let tolerances: Vec<NumericTolerance> = alignment
+            .matched_cols()
+            .map(|(old_col, _)| options.tolerance_for(sheet, (old_col + 1) as usize))
+            .collect();
+
+        for (old_row, new_row) in alignment.matched_rows() {
+            for ((old_col, new_col), tolerance) in alignment.matched_cols().zip(tolerances.iter()) {
+                let old_cell = old_range
+                    .get_value((old_row, old_col))
+                    .unwrap_or(&Data::Empty);
+                let new_cell = new_range
+                    .get_value((new_row, new_col))
+                    .unwrap_or(&Data::Empty);
+
+                if let Some(kind) = compare_data(old_cell, new_cell, tolerance, options) {
+                    cell_diffs.push(CellDiff {
+                        old_type: Some(old_cell.into()),
+                        new_type: Some(new_cell.into()),
+                        old_value: Some(old_cell.into()),
+                        new_value: Some(new_cell.into()),
+                        ..CellDiff::new(
+                            (old_row, old_col),
+                            (new_row, new_col),
+                            kind,
+                            if old_cell != &Data::Empty {
+                                Some(data_to_string(old_cell))
+                            } else {
+                                None
+                            },
+                            if new_cell != &Data::Empty {
+                                Some(data_to_string(new_cell))
+                            } else {
+                                None
+                            },
+                        )
+                    });
+                }
+            }
+        }
+
+        cell_diffs
+    }
+
+    /// collect formula diff in cell range. empty formula calamine fills between formulas is
+    /// regarded as no formula
+    fn collect_cell_formula_diff(
+        old_range: &Range<String>,
+        new_range: &Range<String>,
+        alignment: &Alignment,
+        options: &DiffOptions,
+    ) -> Vec<CellDiff> {
+        let mut cell_diffs: Vec<CellDiff> = vec![];
+
+        for (old_row, new_row) in alignment.matched_rows() {
+            for (old_col, new_col) in alignment.matched_cols() {
+                let old_cell = match old_range.get_value((old_row, old_col)) {
+                    Some(x) if !x.is_empty() => &Data::String(x.to_string()),
+                    _ => &Data::Empty,
+                };
+                let new_cell = match new_range.get_value((new_row, new_col)) {
+                    Some(x) if !x.is_empty() => &Data::String(x.to_string()),
+                    _ => &Data::Empty,
+                };
+
+                let is_equal = match (old_cell, new_cell) {
+                    (Data::String(old_formula), Data::String(new_formula))
+                        if options.formula != FormulaComparison::default() =>
+                    {
+                        let position = |row: u32, col: u32| {
+                            options
+                                .formula
+                                .relative_references
+                                .then_some(((row + 1) as usize, (col + 1) as usize))
+                        };
+                        normalize_formula(old_formula, position(old_row, old_col))
+                            == normalize_formula(new_formula, position(new_row, new_col))
+                    }
+                    _ => old_cell == new_cell,
+                };
 
-                if !cell_diffs.is_empty() {
-                    let sheet_cell_diff = SheetCellDiff {
-                        sheet: sheet.to_owned(),
-                        cells: cell_diffs,
-                    };
-                    self.cell_diffs.push(sheet_cell_diff);
+                if !is_equal {
+                    cell_diffs.push(CellDiff::new(
+                        (old_row, old_col),
+                        (new_row, new_col),
+                        CellDiffKind::Formula,
+                        if old_cell != &Data::Empty {
+                            Some(old_cell.to_string())
+                        } else {
+                            None
+                        },
+                        if new_cell != &Data::Empty {
+                            Some(new_cell.to_string())
+                        } else {
+                            None
+                        },
+                    ));
                 }
+            }
+        }
+
+        cell_diffs
+    }
+
+    /// mark value and type diffs of cells whose formula is there and unchanged as result diffs
+    fn classify_result_diff(
+        cell_diffs: &mut [CellDiff],
+        old_formula: &Range<String>,
+        new_formula: &Range<String>,
+    ) {
+        let formula_changed: HashSet<(usize, usize)> = cell_diffs
+            .iter()
+            .filter(|x| x.kind.is_formula_change())
+            .map(|x| (x.row, x.col))
+            .collect();
+        let has_formula = |range: &Range<String>, row: usize, col: usize| {
+            range
+                .get_value(((row - 1) as u32, (col - 1) as u32))
+                .is_some_and(|x| !x.is_empty())
+        };
+
+        cell_diffs
+            .iter_mut()
+            .filter(|x| matches!(x.kind, CellDiffKind::Value | CellDiffKind::Type))
+            .filter(|x| !formula_changed.contains(&(x.row, x.col)))
+            .filter(|x| {
+                has_formula(old_formula, x.row, x.col)
+                    && has_formula(new_formula, x.new_row, x.new_col)
+            })
+            .for_each(|x| x.kind = CellDiffKind::Result);
+    }
+
+    /// mark formula diffs where formula is replaced by constant, or vice versa,
+    /// with the cell values
+    fn classify_constant_diff(
+        cell_diffs: &mut [CellDiff],
+        old_range: &Range<Data>,
+        new_range: &Range<Data>,
+    ) {
+        let value = |range: &Range<Data>, row: usize, col: usize| {
+            range
+                .get_value(((row - 1) as u32, (col - 1) as u32))
+                .unwrap_or(&Data::Empty)
+                .clone()
+        };
+
+        cell_diffs
+            .iter_mut()
+            .filter(|x| x.kind == CellDiffKind::Formula)
+            .for_each(|x| {
+                let old_value = value(old_range, x.row, x.col);
+                let new_value = value(new_range, x.new_row, x.new_col);
+                let kind = match (&x.old, &x.new) {
+                    (Some(_), None) if new_value != Data::Empty => CellDiffKind::FormulaToConstant,
+                    (None, Some(_)) if old_value != Data::Empty => CellDiffKind::ConstantToFormula,
+                    _ => return,
+                };
+                x.kind = kind;
+                x.old_type = Some((&old_value).into());
+                x.new_type = Some((&new_value).into());
+                x.old_value = Some((&old_value).into());
+                x.new_value = Some((&new_value).into());
+            });
+    }
+
+    /// keep only cell diffs of the kinds, such as `FormulaToConstant` to review values
+    /// pasted over formulas
+    ///
+    /// the other diffs are left as they are. sheets having no diffs left are removed
+    pub fn retain_cell_kinds(&mut self, kinds: &[CellDiffKind]) {
+        self.cell_diffs.iter_mut().for_each(|x| {
+            x.cells.retain(|x| kinds.contains(&x.kind));
+        });
+        self.cell_diffs.retain(|x| {
+            !x.columns.is_empty()
+                || !x.rows.is_empty()
+                || !x.cells.is_empty()
+                || !x.records.is_empty()
+                || !x.duplicate_keys.is_empty()
+                || !x.merged_cells.is_empty()
+        });
+    }
+
+    /// record sheet read error once even if both value and formula passes fail
+    fn push_sheet_error(&mut self, err: Error) {
+        if !self.sheet_errors.contains(&err) {
+            self.sheet_errors.push(err);
+        }
+    }
+}
+
+impl CellDiff {
+    /// init from 0-based (row, col) positions in old / new sheets
+    fn new(
+        old_pos: (u32, u32),
+        new_pos: (u32, u32),
+        kind: CellDiffKind,
+        old: Option<String>,
+        new: Option<String>,
+    ) -> Self {
+        let row = (old_pos.0 + 1) as usize;
+        let col = (old_pos.1 + 1) as usize;
+        let new_row = (new_pos.0 + 1) as usize;
+        let new_col = (new_pos.1 + 1) as usize;
+        CellDiff {
+            row,
+            col,
+            addr: cell_pos_to_address(row, col),
+            new_row,
+            new_col,
+            new_addr: cell_pos_to_address(new_row, new_col),
+            kind,
+            old,
+            new,
+            old_type: None,
+            new_type: None,
+            old_value: None,
+            new_value: None,
+        }
+    }
+}
+
+/// compare cell values by policy. numbers are compared within tolerance if any,
+/// and dates as date time regardless of epoch
+///
+/// return: `None` when equal, `Type` when only type differs, and `Value` otherwise
+fn compare_data(
+    old: &Data,
+    new: &Data,
+    tolerance: &NumericTolerance,
+    options: &DiffOptions,
+) -> Option<CellDiffKind> {
+    if let (Data::DateTime(old), Data::DateTime(new)) = (old, new) {
+        if old.is_datetime() && new.is_datetime() {
+            let is_equal = if is_time_only(old) || is_time_only(new) {
+                // time of day without date is the same in both epochs
+                old.as_f64() == new.as_f64()
+            } else if options.compare_dates_by_day {
+                old.as_datetime().map(|x| x.date()) == new.as_datetime().map(|x| x.date())
             } else {
-                println!("Failed to read sheet: {}", sheet);
+                old.as_datetime() == new.as_datetime()
+            };
+            return (!is_equal).then_some(CellDiffKind::Value);
+        }
+        // durations are the same in both epochs
+        if old.is_duration() && new.is_duration() {
+            return (old.as_f64() != new.as_f64()).then_some(CellDiffKind::Value);
+        }
+    }
+
+    let as_number = |data: &Data| match data {
+        Data::Int(x) => Some(*x as f64),
+        Data::Float(x) => Some(*x),
+        _ => None,
+    };
+    let is_same_type = CellType::from(old) == CellType::from(new);
+    let is_equal = match (as_number(old), as_number(new)) {
+        _ if options.comparison == ComparisonPolicy::Textual => {
+            options.text.normalize(&data_to_string(old))
+                == options.text.normalize(&data_to_string(new))
+        }
+        (Some(old), Some(new))
+            if is_same_type || options.comparison == ComparisonPolicy::NumericEquivalent =>
+        {
+            tolerance.is_equal(old, new)
+        }
+        _ => match (old, new) {
+            (Data::String(old), Data::String(new)) if options.text != TextComparison::default() => {
+                options.text.normalize(old) == options.text.normalize(new)
             }
+            _ => old == new,
+        },
+    };
+
+    if is_equal {
+        None
+    } else if !is_same_type && data_to_string(old) == data_to_string(new) {
+        Some(CellDiffKind::Type)
+    } else {
+        Some(CellDiffKind::Value)
+    }
+}
+
+/// cell texts of each row
+fn range_lines(range: &Range<Data>, rows: std::ops::Range<u32>, cols: &[u32]) -> Vec<Vec<String>> {
+    rows.map(|row| {
+        cols.iter()
+            .map(|&col| {
+                range
+                    .get_value((row, col))
+                    .map(data_to_string)
+                    .unwrap_or_default()
+            })
+            .collect()
+    })
+    .collect()
+}
+
+/// swap rows and cols of lines
+fn transpose(lines: Vec<Vec<String>>) -> Vec<Vec<String>> {
+    let len = lines.first().map_or(0, |x| x.len());
+    let mut ret: Vec<Vec<String>> = vec![Vec::with_capacity(lines.len()); len];
+    for line in lines {
+        for (i, text) in line.into_iter().enumerate() {
+            ret[i].push(text);
         }
     }
+    ret
+}
+
+/// texts of style properties changed such as `bold: false; fill: none`. `None` when equal
+fn style_diff_texts(old: &CellStyle, new: &CellStyle) -> Option<(String, String)> {
+    let (old, new): (Vec<String>, Vec<String>) = old
+        .properties()
+        .into_iter()
+        .zip(new.properties())
+        .filter(|((_, old), (_, new))| old != new)
+        .map(|((name, old), (_, new))| (format!("{}: {}", name, old), format!("{}: {}", name, new)))
+        .unzip();
+    (!old.is_empty()).then(|| (old.join("; "), new.join("; ")))
 }
@@ -0,0 +1,115 @@
+use std::fmt;
+
+use calamine::{XlsError, XlsxError};
+
+use super::package::PackageError;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// error on collecting diff
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Error {
+    /// file cannot be opened
+    Open { filepath: String, reason: String },
+    /// file format is not supported
+    UnsupportedFormat { filepath: String },
+    /// sheet selected by `#` is not in workbook
+    SheetNotFound { filepath: String, sheet: String },
+    /// sheet cannot be read
+    SheetRead {
+        filepath: String,
+        sheet: String,
+        reason: String,
+    },
+    /// file is broken and cannot be parsed as workbook
+    CorruptArchive { filepath: String, reason: String },
+    /// key col specified is not in sheet
+    KeyNotFound {
+        filepath: String,
+        sheet: String,
+        key: String,
+    },
+    /// sheet pattern in options cannot be compiled
+    InvalidSheetPattern { pattern: String, reason: String },
+    /// cell range in options has row or col 0 while they are 1-based
+    InvalidCellRange { sheet: String, range: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Open { filepath, reason } => write!(f, "Cannot open {}: {}", filepath, reason),
+            Error::UnsupportedFormat { filepath } => {
+                write!(f, "Unsupported file format: {}", filepath)
+            }
+            Error::SheetNotFound { filepath, sheet } => {
+                write!(f, "Sheet not found: {} in {}", sheet, filepath)
+            }
+            Error::SheetRead {
+                filepath,
+                sheet,
+                reason,
+            } => write!(
+                f,
+                "Failed to read sheet: {} in {}: {}",
+                sheet, filepath, reason
+            ),
+            Error::CorruptArchive { filepath, reason } => {
+                write!(f, "Corrupt archive {}: {}", filepath, reason)
+            }
+            Error::KeyNotFound {
+                filepath,
+                sheet,
+                key,
+            } => write!(
+                f,
+                "Key column not found: {} in {} in {}",
+                key, sheet, filepath
+            ),
+            Error::InvalidSheetPattern { pattern, reason } => {
+                write!(f, "Invalid sheet pattern: {}: {}", pattern, reason)
+            }
+            Error::InvalidCellRange { sheet, range } => {
+                write!(f, "Invalid cell range: {} in {}", range, sheet)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// error on opening workbook
+    pub(crate) fn from_open(filepath: &str, err: calamine::Error) -> Self {
+        let filepath = filepath.to_owned();
+        let reason = err.to_string();
+        match err {
+            calamine::Error::Io(_)
+            | calamine::Error::Xlsx(XlsxError::Io(_) | XlsxError::Password)
+            | calamine::Error::Xls(XlsError::Io(_) | XlsError::Password) => {
+                Error::Open { filepath, reason }
+            }
+            _ => Error::CorruptArchive { filepath, reason },
+        }
+    }
+
+    /// error on reading xlsx package parts calamine does not read
+    pub(crate) fn from_package_open(filepath: &str, err: PackageError) -> Self {
+        let filepath = filepath.to_owned();
+        let reason = err.to_string();
+        match err {
+            PackageError::Io(_) => Error::Open { filepath, reason },
+            _ => Error::CorruptArchive { filepath, reason },
+        }
+    }
+
+    /// error on reading sheet
+    pub(crate) fn from_sheet_read(filepath: &str, sheet: &str, err: impl fmt::Display) -> Self {
+        Error::SheetRead {
+            filepath: filepath.to_owned(),
+            sheet: sheet.to_owned(),
+            reason: err.to_string(),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use super::address::{col_to_letters, letters_to_col};
+
+/// formula token
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Token {
+    /// `"text"`, kept as it is
+    Text(String),
+    /// `'Sheet 1'` or `[Table1]`, kept as it is
+    Quoted(String),
+    /// function name, cell reference, number, name and so on
+    Word(String),
+    /// operator or punctuation
+    Symbol(char),
+    Space,
+}
+
+/// canonical formula: whitespace removed, function names, names and cell references such as
+/// `A1` or whole col `A:A` in upper case. sheet names such as `Tax1` in `Tax1!A1` are kept
+/// as they are
+///
+/// when `cell` (1-based row and col of the formula) is given, relative references are
+/// converted to R1C1 style such as `R[-1]C` so that copied formulas are equal
+pub fn normalize_formula(formula: &str, cell: Option<(usize, usize)>) -> String {
+    let tokens = tokenize(formula.strip_prefix('=').unwrap_or(formula));
+
+    let mut ret = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Text(x) | Token::Quoted(x) => ret.push_str(x),
+            Token::Word(x) => {
+                let is_function = matches!(tokens.get(i + 1), Some(Token::Symbol('(')));
+                // `Tax1!A1` or the first of `Tax1:Tax3!A1`
+                let is_sheet = match tokens.get(i + 1) {
+                    Some(Token::Symbol('!')) => true,
+                    Some(Token::Symbol(':')) => matches!(
+                        (tokens.get(i + 2), tokens.get(i + 3)),
+                        (Some(Token::Word(_)), Some(Token::Symbol('!')))
+                    ),
+                    _ => false,
+                };
+                if is_sheet {
+                    ret.push_str(x);
+                } else if is_function {
+                    ret.push_str(&x.to_uppercase());
+                } else {
+                    match parse_reference(x).or_else(|| parse_line_reference(&tokens, i)) {
+                        Some(reference) => ret.push_str(&reference.format(cell)),
+                        // name, number or boolean, all of which are case insensitive
+                        None => ret.push_str(&x.to_uppercase()),
+                    }
+                }
+            }
+            Token::Symbol(x) => ret.push(*x),
+            // space between operands is intersection operator
+            Token::Space => {
+                let is_operand_end = |x: Option<&Token>| {
+                    matches!(
+                        x,
+                        Some(Token::Word(_) | Token::Quoted(_) | Token::Symbol(')'))
+                    )
+                };
+                let is_operand_start = |x: Option<&Token>| {
+                    matches!(
+                        x,
+                        Some(Token::Word(_) | Token::Quoted(_) | Token::Symbol('('))
+                    )
+                };
+                let prev = i.checked_sub(1).and_then(|x| tokens.get(x));
+                if is_operand_end(prev) && is_operand_start(tokens.get(i + 1)) {
+                    ret.push(' ');
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// split formula into tokens. consecutive whitespace is one `Space`
+fn tokenize(formula: &str) -> Vec<Token> {
+    let chars: Vec<char> = formula.chars().collect();
+    let mut ret: Vec<Token> = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' => {
+                // quote char doubled is escaped one
+                let start = i;
+                i += 1;
+                while i < chars.len() {
+                    if chars[i] == c {
+                        if chars.get(i + 1) == Some(&c) {
+                            i += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                    i += 1;
+                }
+                i = (i + 1).min(chars.len());
+                let quoted: String = chars[start..i].iter().collect();
+                ret.push(if c == '"' {
+                    Token::Text(quoted)
+                } else {
+                    Token::Quoted(quoted)
+                });
+                continue;
+            }
+            '[' => {
+                let start = i;
+                let mut depth = 0;
+                while i < chars.len() {
+                    match chars[i] {
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        _ => (),
+                    }
+                    i += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                ret.push(Token::Quoted(chars[start..i].iter().collect()));
+                continue;
+            }
+            _ if c.is_whitespace() => {
+                while i < chars.len() && chars[i].is_whitespace() {
+                    i += 1;
+                }
+                ret.push(Token::Space);
+                continue;
+            }
+            _ if is_word_char(c) => {
+                let start = i;
+                while i < chars.len() && is_word_char(chars[i]) {
+                    i += 1;
+                }
+                ret.push(Token::Word(chars[start..i].iter().collect()));
+                continue;
+            }
+            _ => ret.push(Token::Symbol(c)),
+        }
+        i += 1;
+    }
+    ret
+}
+
+/// char in function name, cell reference, number or name
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '$' | '\\')
+}
+
+/// A1 style cell reference, or one end of whole col `A:C` or whole row `1:3` without the other
+struct Reference {
+    col: Option<usize>,
+    is_col_absolute: bool,
+    row: Option<usize>,
+    is_row_absolute: bool,
+}
+
+impl Reference {
+    /// A1 style in upper case, or R1C1 style relative to cell
+    fn format(&self, cell: Option<(usize, usize)>) -> String {
+        let dollar = |is_absolute: bool| if is_absolute { "$" } else { "" };
+        match cell {
+            Some((row, col)) => {
+                let part = |prefix: char, index: Option<usize>, is_absolute: bool, base: usize| {
+                    match index {
+                        Some(index) if is_absolute => format!("{}{}", prefix, index),
+                        Some(index) if index == base => prefix.to_string(),
+                        Some(index) => format!("{}[{}]", prefix, index as i64 - base as i64),
+                        None => String::new(),
+                    }
+                };
+                format!(
+                    "{}{}",
+                    part('R', self.row, self.is_row_absolute, row),
+                    part('C', self.col, self.is_col_absolute, col)
+                )
+            }
+            None => format!(
+                "{}{}{}{}",
+                self.col.map_or("", |_| dollar(self.is_col_absolute)),
+                self.col.map(col_to_letters).unwrap_or_default(),
+                self.row.map_or("", |_| dollar(self.is_row_absolute)),
+                self.row.map(|x| x.to_string()).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// parse `A1`, `$A1`, `A$1` or `$A$1`
+fn parse_reference(word: &str) -> Option<Reference> {
+    let (is_col_absolute, rest) = match word.strip_prefix('$') {
+        Some(rest) => (true, rest),
+        None => (false, word),
+    };
+    let letters_len = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let (letters, rest) = rest.split_at(letters_len);
+    let (is_row_absolute, digits) = match rest.strip_prefix('$') {
+        Some(digits) => (true, digits),
+        None => (false, rest),
+    };
+    if letters.is_empty()
+        || 3 < letters.len()
+        || digits.is_empty()
+        || !digits.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    Some(Reference {
+        col: Some(letters_to_col(letters)?),
+        is_col_absolute,
+        row: Some(digits.parse().ok()?),
+        is_row_absolute,
+    })
+}
+
+/// parse word at index as one end of whole col `$A:C` or whole row `1:$3`,
+/// whose ends are both cols or both rows
+fn parse_line_reference(tokens: &[Token], index: usize) -> Option<Reference> {
+    let word = |index: Option<usize>| match index.and_then(|x| tokens.get(x)) {
+        Some(Token::Word(x)) => Some(x.as_str()),
+        _ => None,
+    };
+    let other = match (
+        tokens.get(index + 1),
+        index.checked_sub(1).map(|x| &tokens[x]),
+    ) {
+        (Some(Token::Symbol(':')), _) => word(Some(index + 2)),
+        (_, Some(Token::Symbol(':'))) => word(index.checked_sub(2)),
+        _ => None,
+    }?;
+    let reference = parse_line_end(word(Some(index))?)?;
+    let other = parse_line_end(other)?;
+    (reference.col.is_some() == other.col.is_some()).then_some(reference)
+}
+
+/// parse `A`, `$A`, `1` or `$1`
+fn parse_line_end(word: &str) -> Option<Reference> {
+    let (is_absolute, rest) = match word.strip_prefix('$') {
+        Some(rest) => (true, rest),
+        None => (false, word),
+    };
+    if !rest.is_empty() && rest.len() <= 3 && rest.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Some(Reference {
+            col: Some(letters_to_col(rest)?),
+            is_col_absolute: is_absolute,
+            row: None,
+            is_row_absolute: false,
+        });
+    }
+    if !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()) {
+        return Some(Reference {
+            col: None,
+            is_col_absolute: false,
+            row: Some(rest.parse().ok()?),
+            is_row_absolute: is_absolute,
+        });
+    }
+    None
+}
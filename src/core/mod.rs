@@ -1,3 +1,12 @@
+pub mod address;
+mod align;
+mod delimited;
 pub mod diff;
+pub mod error;
+mod formula;
+pub mod options;
+mod package;
+mod record;
 pub mod unified_format;
 mod utils;
+pub mod workbook;
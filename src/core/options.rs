@@ -0,0 +1,327 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use regex::Regex;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use unicode_normalization::UnicodeNormalization as _;
+
+use super::{
+    address::{col_to_letters, CellRange},
+    error::Error,
+};
+
+/// options to collect diff
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DiffOptions {
+    /// how to read csv / tsv files
+    pub csv: CsvOptions,
+    /// match rows by content so that inserted / deleted rows are reported as row diffs
+    /// instead of shifting all the following cells
+    pub align_rows: bool,
+    /// match cols so that inserted / deleted / moved cols are reported as col diffs
+    pub align_columns: Option<ColumnAlignment>,
+    /// key cols by sheet name. sheets keyed are compared as records matched by key
+    /// instead of cell by cell
+    pub keys: HashMap<String, Vec<KeyColumn>>,
+    /// key col names by excel table name. rows of tables keyed are compared as records
+    /// matched by key, with fields matched by col name
+    pub table_keys: HashMap<String, Vec<String>>,
+    /// pair sheets found only in either file as renamed when their cell values are shared
+    /// at least this ratio (0.0 to 1.0)
+    pub sheet_rename_similarity: Option<f64>,
+    /// numbers differing within this are regarded as equal
+    pub tolerance: NumericTolerance,
+    /// tolerance by sheet name, which overrides `tolerance`
+    pub sheet_tolerances: HashMap<String, SheetTolerance>,
+    /// how values of different types are compared
+    pub comparison: ComparisonPolicy,
+    /// compare dates ignoring time of day
+    pub compare_dates_by_day: bool,
+    /// compare cell formatting of xlsx: number format, font, fill, borders and alignment
+    pub compare_styles: bool,
+    /// how text values are normalized before compared
+    pub text: TextComparison,
+    /// how formulas are normalized before compared
+    pub formula: FormulaComparison,
+    /// sheets and cell ranges to compare
+    pub sheets: SheetSelection,
+}
+
+impl DiffOptions {
+    /// tolerance for col in sheet: col one, sheet one, or global one in this order
+    pub(crate) fn tolerance_for(&self, sheet: &str, col: usize) -> NumericTolerance {
+        match self.sheet_tolerances.get(sheet) {
+            Some(sheet_tolerance) => sheet_tolerance
+                .columns
+                .get(&col)
+                .copied()
+                .or(sheet_tolerance.tolerance)
+                .unwrap_or(self.tolerance),
+            None => self.tolerance,
+        }
+    }
+}
+
+/// how values of different types are compared
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ComparisonPolicy {
+    /// type and value must be equal. integer `1` vs float `1.0` is type diff
+    #[default]
+    Strict,
+    /// integer and float are compared as number. number vs text is still type diff
+    NumericEquivalent,
+    /// compared as text regardless of type
+    Textual,
+}
+
+/// text normalization before comparison. all off by default
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TextComparison {
+    /// ignore leading / trailing whitespace
+    pub trim: bool,
+    /// regard consecutive whitespace as one space
+    pub collapse_whitespace: bool,
+    pub ignore_case: bool,
+    /// regard text in the same normalization form as equal
+    pub normalization: Option<UnicodeNormalization>,
+}
+
+impl TextComparison {
+    /// text normalized as specified
+    pub fn normalize(&self, text: &str) -> String {
+        let mut ret: String = match self.normalization {
+            Some(UnicodeNormalization::Nfc) => text.nfc().collect(),
+            Some(UnicodeNormalization::Nfkc) => text.nfkc().collect(),
+            None => text.to_owned(),
+        };
+        if self.collapse_whitespace {
+            ret = ret.split_whitespace().collect::<Vec<&str>>().join(" ");
+            // keep leading / trailing whitespace unless trimmed
+            if !self.trim {
+                if text.starts_with(char::is_whitespace) {
+                    ret.insert(0, ' ');
+                }
+                if text.ends_with(char::is_whitespace) && !ret.ends_with(' ') {
+                    ret.push(' ');
+                }
+            }
+        } else if self.trim {
+            ret = ret.trim().to_owned();
+        }
+        if self.ignore_case {
+            ret = ret.to_lowercase();
+        }
+        ret
+    }
+}
+
+/// formula normalization before comparison. all off by default
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FormulaComparison {
+    /// ignore whitespace and case of function names and cell references
+    pub normalize: bool,
+    /// compare relative references as R1C1 style so that formulas copied down / across,
+    /// or shifted by inserted rows, are equal. implies `normalize`
+    pub relative_references: bool,
+}
+
+/// unicode normalization form
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum UnicodeNormalization {
+    /// canonical composition: `e` + combining acute accent equals `é`
+    Nfc,
+    /// compatibility composition: also full-width `Ａ` equals `A` and half-width `ｶ` equals `カ`
+    Nfkc,
+}
+
+/// absolute and / or relative difference allowed between numbers. zero means exact match
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NumericTolerance {
+    /// `|old - new| <= absolute`
+    pub absolute: f64,
+    /// `|old - new| <= relative * max(|old|, |new|)`
+    pub relative: f64,
+}
+
+impl NumericTolerance {
+    /// whether numbers are equal within either tolerance
+    pub fn is_equal(&self, old: f64, new: f64) -> bool {
+        let delta = (old - new).abs();
+        old == new || delta <= self.absolute || delta <= self.relative * old.abs().max(new.abs())
+    }
+}
+
+/// tolerance of sheet
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SheetTolerance {
+    /// for the whole sheet. `None` to use global one
+    pub tolerance: Option<NumericTolerance>,
+    /// by 1-based col number such as `1` for col A
+    pub columns: HashMap<usize, NumericTolerance>,
+}
+
+/// how to match cols between old and new sheets
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ColumnAlignment {
+    /// by header text in the first row
+    Header,
+    /// by values the cols share
+    Content,
+    /// by header text, and then by values for cols unmatched
+    HeaderAndContent,
+}
+
+/// col identifying record. several cols make composite key
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum KeyColumn {
+    /// 1-based col number such as `1` for col A
+    Col(usize),
+    /// header text in the first row
+    Header(String),
+}
+
+impl fmt::Display for KeyColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyColumn::Col(col) => write!(f, "{}", col_to_letters(*col)),
+            KeyColumn::Header(header) => write!(f, "{}", header),
+        }
+    }
+}
+
+/// sheets and cell ranges to compare. all sheets and cells by default
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SheetSelection {
+    /// compare only sheets matching any of them. all sheets when empty
+    pub include: Vec<SheetPattern>,
+    /// skip sheets matching any of them, even when included
+    pub exclude: Vec<SheetPattern>,
+    /// compare only cells in these ranges by sheet name. whole sheet when absent
+    pub ranges: HashMap<String, Vec<CellRange>>,
+}
+
+/// sheet name pattern
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SheetPattern {
+    /// exact sheet name
+    Name(String),
+    /// glob such as `Calc*`, where `*` matches any chars and `?` one char
+    Glob(String),
+    /// regular expression such as `^Calc\d+$`
+    Regex(String),
+}
+
+impl SheetPattern {
+    /// compile to regex matching whole sheet name
+    fn to_regex(&self) -> Result<Regex, Error> {
+        let pattern = match self {
+            SheetPattern::Name(name) => format!("^{}$", regex::escape(name)),
+            SheetPattern::Glob(glob) => {
+                let pattern: String = glob
+                    .chars()
+                    .map(|c| match c {
+                        '*' => ".*".to_owned(),
+                        '?' => ".".to_owned(),
+                        c => regex::escape(&c.to_string()),
+                    })
+                    .collect();
+                format!("^{}$", pattern)
+            }
+            SheetPattern::Regex(pattern) => pattern.to_owned(),
+        };
+        Regex::new(&pattern).map_err(|err| Error::InvalidSheetPattern {
+            pattern: self.to_string(),
+            reason: err.to_string(),
+        })
+    }
+}
+
+impl FromStr for SheetPattern {
+    type Err = String;
+
+    /// `/regex/`, glob with `*` or `?`, or else exact name.
+    /// they are unambiguous because sheet names cannot have `/`, `*` or `?`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err("Empty sheet pattern".to_owned());
+        }
+        let pattern = match s.strip_prefix('/').and_then(|x| x.strip_suffix('/')) {
+            Some(pattern) => SheetPattern::Regex(pattern.to_owned()),
+            None if s.contains(['*', '?']) => SheetPattern::Glob(s.to_owned()),
+            None => SheetPattern::Name(s.to_owned()),
+        };
+        Ok(pattern)
+    }
+}
+
+impl fmt::Display for SheetPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SheetPattern::Name(name) => write!(f, "{}", name),
+            SheetPattern::Glob(glob) => write!(f, "{}", glob),
+            SheetPattern::Regex(pattern) => write!(f, "/{}/", pattern),
+        }
+    }
+}
+
+/// compiled include / exclude patterns
+pub(crate) struct SheetFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl SheetFilter {
+    /// compile patterns, returning error on invalid regex
+    pub(crate) fn new(selection: &SheetSelection) -> Result<Self, Error> {
+        let compile = |patterns: &[SheetPattern]| {
+            patterns
+                .iter()
+                .map(SheetPattern::to_regex)
+                .collect::<Result<Vec<Regex>, Error>>()
+        };
+        Ok(SheetFilter {
+            include: compile(&selection.include)?,
+            exclude: compile(&selection.exclude)?,
+        })
+    }
+
+    /// whether sheet is to be compared
+    pub(crate) fn is_selected(&self, sheet: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|x| x.is_match(sheet)))
+            && !self.exclude.iter().any(|x| x.is_match(sheet))
+    }
+}
+
+/// options to read csv / tsv files
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CsvOptions {
+    /// field delimiter. when `None`, tab for `.tsv` and comma for others
+    pub delimiter: Option<u8>,
+    /// quote char
+    pub quote: u8,
+    /// encoding label such as `utf-8`, `shift_jis` or `windows-1252`
+    pub encoding: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: None,
+            quote: b'"',
+            encoding: "utf-8".to_owned(),
+        }
+    }
+}
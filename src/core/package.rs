@@ -0,0 +1,918 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Cursor, Read},
+    rc::Rc,
+};
+
+use quick_xml::{
+    escape::unescape,
+    events::{attributes::Attribute, BytesStart, Event},
+    Reader,
+};
+use zip::{result::ZipError, ZipArchive};
+
+use super::address::{address_to_cell_pos, CellRange};
+
+/// error on reading xlsx package. own one as zip / xml crates may differ from calamine's
+#[derive(Debug)]
+pub(crate) enum PackageError {
+    Io(std::io::Error),
+    Zip(ZipError),
+    Xml(quick_xml::Error),
+}
+
+impl fmt::Display for PackageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackageError::Io(err) => write!(f, "I/O error: {}", err),
+            PackageError::Zip(err) => write!(f, "Zip error: {}", err),
+            PackageError::Xml(err) => write!(f, "Xml error: {}", err),
+        }
+    }
+}
+
+/// parts of xlsx package calamine does not read
+pub(crate) struct XlsxPackage {
+    archive: ZipArchive<Cursor<Rc<[u8]>>>,
+    /// name and part path of each sheet, read from workbook once
+    sheets: Option<Vec<(String, String)>>,
+    /// cell styles by style id, read from styles once
+    styles: Option<Vec<CellStyle>>,
+}
+
+/// relationship in `.rels` part
+struct Relationship {
+    id: String,
+    /// type url such as `.../relationships/comments`
+    rel_type: String,
+    /// part path in package
+    target: String,
+}
+
+/// defined name (named range)
+pub(crate) struct DefinedName {
+    pub name: String,
+    /// sheet name for sheet-scoped name. `None` for workbook-scoped one
+    pub scope: Option<String>,
+    /// reference or formula such as `Sheet1!$B$2`
+    pub reference: String,
+}
+
+/// excel table (list object)
+pub(crate) struct Table {
+    /// id unique in workbook, kept on rename
+    pub id: String,
+    pub name: String,
+    pub sheet: String,
+    /// range including header and totals rows
+    pub range: CellRange,
+    /// number of header rows at top, 0 or 1
+    pub header_row_count: usize,
+    /// number of totals rows at bottom, 0 or 1
+    pub totals_row_count: usize,
+    /// id and name of each col in order. id is kept on rename
+    pub columns: Vec<(String, String)>,
+}
+
+impl Table {
+    /// range of data rows without header and totals rows, none when no data row
+    pub fn data_range(&self) -> Option<CellRange> {
+        let start_row = self.range.start_row + self.header_row_count;
+        let end_row = self.range.end_row.checked_sub(self.totals_row_count)?;
+        (start_row <= end_row).then_some(CellRange {
+            start_row,
+            end_row,
+            ..self.range
+        })
+    }
+
+    /// 0-based col and name of each table col
+    pub fn fields(&self) -> Vec<(u32, String)> {
+        self.columns
+            .iter()
+            .enumerate()
+            .map(|(i, (_, name))| ((self.range.start_col - 1 + i) as u32, name.to_owned()))
+            .collect()
+    }
+}
+
+/// comment in threaded comments part
+struct ThreadedComment {
+    pos: (u32, u32),
+    id: String,
+    parent_id: Option<String>,
+    person_id: String,
+    text: String,
+    done: bool,
+}
+
+/// cell formatting resolved from cell format (`xf`) in styles part
+#[derive(Clone, PartialEq, Debug, Default)]
+pub(crate) struct CellStyle {
+    /// format code such as `0.00%`
+    pub number_format: String,
+    pub font: Font,
+    /// pattern and color such as `solid #FFFF00`
+    pub fill: String,
+    /// style and color of left, right, top and bottom borders such as `thin #000000`
+    pub borders: [String; 4],
+    pub alignment: TextAlignment,
+}
+
+impl CellStyle {
+    /// property names and texts in order. empty ones are shown as their defaults
+    pub fn properties(&self) -> Vec<(&'static str, String)> {
+        let or =
+            |text: &str, default: &str| if text.is_empty() { default } else { text }.to_owned();
+        let [left, right, top, bottom] = &self.borders;
+        vec![
+            ("number format", or(&self.number_format, "General")),
+            ("font", self.font.name.to_owned()),
+            ("size", self.font.size.to_owned()),
+            ("bold", self.font.bold.to_string()),
+            ("italic", self.font.italic.to_string()),
+            ("underline", or(&self.font.underline, "none")),
+            ("strike", self.font.strike.to_string()),
+            ("color", or(&self.font.color, "auto")),
+            ("fill", or(&self.fill, "none")),
+            ("left border", or(left, "none")),
+            ("right border", or(right, "none")),
+            ("top border", or(top, "none")),
+            ("bottom border", or(bottom, "none")),
+            ("horizontal", or(&self.alignment.horizontal, "general")),
+            ("vertical", or(&self.alignment.vertical, "bottom")),
+            ("wrap", self.alignment.wrap.to_string()),
+            ("indent", or(&self.alignment.indent, "0")),
+            ("rotation", or(&self.alignment.rotation, "0")),
+        ]
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub(crate) struct Font {
+    pub name: String,
+    /// in points such as `11`
+    pub size: String,
+    pub bold: bool,
+    pub italic: bool,
+    /// such as `single` or `double`
+    pub underline: String,
+    pub strike: bool,
+    pub color: String,
+}
+
+/// text alignment in cell
+#[derive(Clone, PartialEq, Debug, Default)]
+pub(crate) struct TextAlignment {
+    pub horizontal: String,
+    pub vertical: String,
+    pub wrap: bool,
+    pub indent: String,
+    pub rotation: String,
+}
+
+/// cell styles of sheet
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SheetStyles {
+    /// style of cells without style specified
+    pub default: CellStyle,
+    /// by 0-based (row, col) of cells with style specified
+    pub cells: HashMap<(u32, u32), CellStyle>,
+}
+
+/// cell format (`xf`) referring to shared fonts, fills and borders by index
+#[derive(Default)]
+struct CellFormat {
+    number_format_id: usize,
+    font_id: usize,
+    fill_id: usize,
+    border_id: usize,
+    alignment: TextAlignment,
+}
+
+impl XlsxPackage {
+    /// open package read by calamine as well
+    pub fn new(bytes: Rc<[u8]>) -> Result<Self, PackageError> {
+        let archive = ZipArchive::new(Cursor::new(bytes)).map_err(PackageError::Zip)?;
+        Ok(XlsxPackage {
+            archive,
+            sheets: None,
+            styles: None,
+        })
+    }
+
+    /// cell comments by 0-based (row, col) as `author: text`
+    ///
+    /// threaded comments are joined with ` / ` in order and marked when resolved.
+    /// they take place of legacy comments excel writes along with them
+    pub fn comments(&mut self, sheet: &str) -> Result<HashMap<(u32, u32), String>, PackageError> {
+        let Some(sheet_path) = self.sheet_path(sheet)? else {
+            return Ok(HashMap::new());
+        };
+        let rels = self.relationships(&sheet_path)?;
+
+        let mut ret: HashMap<(u32, u32), String> = HashMap::new();
+        for rel in rels.iter().filter(|x| x.rel_type.ends_with("/comments")) {
+            if let Some(xml) = self.read_part(&rel.target)? {
+                ret.extend(parse_comments(&xml).map_err(PackageError::Xml)?);
+            }
+        }
+
+        let mut threads: Vec<ThreadedComment> = vec![];
+        for rel in rels
+            .iter()
+            .filter(|x| x.rel_type.ends_with("/threadedComment"))
+        {
+            if let Some(xml) = self.read_part(&rel.target)? {
+                threads.extend(parse_threaded_comments(&xml).map_err(PackageError::Xml)?);
+            }
+        }
+        if threads.is_empty() {
+            return Ok(ret);
+        }
+
+        let persons = self.persons()?;
+        let entry = |x: &ThreadedComment| {
+            let author = persons.get(&x.person_id).map_or("", |x| x.as_str());
+            format!("{}: {}", author, x.text)
+        };
+        for top in threads.iter().filter(|x| x.parent_id.is_none()) {
+            let entries: Vec<String> = threads
+                .iter()
+                .filter(|x| x.id == top.id || x.parent_id.as_ref() == Some(&top.id))
+                .map(entry)
+                .collect();
+            let mut text = entries.join(" / ");
+            if top.done {
+                text.push_str(" (resolved)");
+            }
+            ret.insert(top.pos, text);
+        }
+        Ok(ret)
+    }
+
+    /// defined names with scopes, which calamine leaves out
+    pub fn defined_names(&mut self) -> Result<Vec<DefinedName>, PackageError> {
+        let Some(xml) = self.read_part("xl/workbook.xml")? else {
+            return Ok(vec![]);
+        };
+        let mut sheets: Vec<String> = vec![];
+        let mut ret: Vec<DefinedName> = vec![];
+        // name being read
+        let mut defined_name: Option<DefinedName> = None;
+
+        let mut reader = Reader::from_str(&xml);
+        loop {
+            match reader.read_event().map_err(PackageError::Xml)? {
+                Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"sheet" => {
+                    sheets.push(
+                        attribute(&e, b"name")
+                            .map_err(PackageError::Xml)?
+                            .unwrap_or_default(),
+                    );
+                }
+                Event::Start(e) if e.local_name().as_ref() == b"definedName" => {
+                    let name = attribute(&e, b"name").map_err(PackageError::Xml)?;
+                    // index of sheet in workbook order
+                    let scope = attribute(&e, b"localSheetId")
+                        .map_err(PackageError::Xml)?
+                        .and_then(|x| x.parse::<usize>().ok())
+                        .and_then(|x| sheets.get(x).cloned());
+                    defined_name = name.map(|name| DefinedName {
+                        name,
+                        scope,
+                        reference: String::new(),
+                    });
+                }
+                Event::Text(e) => {
+                    if let Some(defined_name) = defined_name.as_mut() {
+                        let text = e.unescape().map_err(PackageError::Xml)?;
+                        defined_name.reference.push_str(&text);
+                    }
+                }
+                Event::End(e) if e.local_name().as_ref() == b"definedName" => {
+                    ret.extend(defined_name.take());
+                }
+                Event::Eof => break,
+                _ => (),
+            }
+        }
+        Ok(ret)
+    }
+
+    /// tables of all sheets in workbook order
+    pub fn tables(&mut self) -> Result<Vec<Table>, PackageError> {
+        let mut ret: Vec<Table> = vec![];
+        for (sheet, sheet_path) in self.sheets()? {
+            let rels = self.relationships(&sheet_path)?;
+            for rel in rels.iter().filter(|x| x.rel_type.ends_with("/table")) {
+                if let Some(xml) = self.read_part(&rel.target)? {
+                    ret.extend(parse_table(&xml, &sheet).map_err(PackageError::Xml)?);
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// styles of cells in sheet. cells without style are left out as default one
+    pub fn cell_styles(&mut self, sheet: &str) -> Result<SheetStyles, PackageError> {
+        let Some(sheet_path) = self.sheet_path(sheet)? else {
+            return Ok(SheetStyles::default());
+        };
+        let Some(xml) = self.read_part(&sheet_path)? else {
+            return Ok(SheetStyles::default());
+        };
+        let ids = parse_cell_style_ids(&xml).map_err(PackageError::Xml)?;
+
+        let styles = self.styles()?;
+        let default = styles.first().cloned().unwrap_or_default();
+        let cells = ids
+            .into_iter()
+            .filter_map(|(pos, id)| styles.get(id).map(|x| (pos, x.to_owned())))
+            .collect();
+        Ok(SheetStyles { default, cells })
+    }
+
+    /// cell styles by style id, parsed on first call
+    fn styles(&mut self) -> Result<&[CellStyle], PackageError> {
+        if self.styles.is_none() {
+            let rels = self.relationships("xl/workbook.xml")?;
+            let styles_path = rels
+                .iter()
+                .find(|x| x.rel_type.ends_with("/styles"))
+                .map_or("xl/styles.xml".to_owned(), |x| x.target.to_owned());
+            let styles = match self.read_part(&styles_path)? {
+                Some(xml) => parse_styles(&xml).map_err(PackageError::Xml)?,
+                None => vec![],
+            };
+            self.styles = Some(styles);
+        }
+        Ok(self.styles.as_deref().unwrap_or_default())
+    }
+
+    /// display names of threaded comment authors by person id
+    fn persons(&mut self) -> Result<HashMap<String, String>, PackageError> {
+        let rels = self.relationships("xl/workbook.xml")?;
+        let mut ret: HashMap<String, String> = HashMap::new();
+        for rel in rels.iter().filter(|x| x.rel_type.ends_with("/person")) {
+            let Some(xml) = self.read_part(&rel.target)? else {
+                continue;
+            };
+            let mut reader = Reader::from_str(&xml);
+            loop {
+                match reader.read_event().map_err(PackageError::Xml)? {
+                    Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"person" => {
+                        if let (Some(id), Some(name)) = (
+                            attribute(&e, b"id").map_err(PackageError::Xml)?,
+                            attribute(&e, b"displayName").map_err(PackageError::Xml)?,
+                        ) {
+                            ret.insert(id, name);
+                        }
+                    }
+                    Event::Eof => break,
+                    _ => (),
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// worksheet part path of sheet
+    fn sheet_path(&mut self, sheet: &str) -> Result<Option<String>, PackageError> {
+        Ok(self
+            .sheets()?
+            .into_iter()
+            .find(|(name, _)| name == sheet)
+            .map(|(_, path)| path))
+    }
+
+    /// name and part path of each sheet in workbook order, parsed on first call
+    fn sheets(&mut self) -> Result<Vec<(String, String)>, PackageError> {
+        if let Some(sheets) = &self.sheets {
+            return Ok(sheets.clone());
+        }
+        let sheets = self.parse_sheets()?;
+        self.sheets = Some(sheets.clone());
+        Ok(sheets)
+    }
+
+    /// name and part path of each sheet in workbook order
+    fn parse_sheets(&mut self) -> Result<Vec<(String, String)>, PackageError> {
+        let Some(xml) = self.read_part("xl/workbook.xml")? else {
+            return Ok(vec![]);
+        };
+        let mut sheets: Vec<(String, String)> = vec![];
+        let mut reader = Reader::from_str(&xml);
+        loop {
+            match reader.read_event().map_err(PackageError::Xml)? {
+                Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"sheet" => {
+                    if let (Some(name), Some(rel_id)) = (
+                        attribute(&e, b"name").map_err(PackageError::Xml)?,
+                        relationship_id(&e).map_err(PackageError::Xml)?,
+                    ) {
+                        sheets.push((name, rel_id));
+                    }
+                }
+                Event::Eof => break,
+                _ => (),
+            }
+        }
+
+        let rels = self.relationships("xl/workbook.xml")?;
+        Ok(sheets
+            .into_iter()
+            .filter_map(|(name, rel_id)| {
+                rels.iter()
+                    .find(|x| x.id == rel_id)
+                    .map(|x| (name, x.target.to_owned()))
+            })
+            .collect())
+    }
+
+    /// relationships of part, with targets resolved to part paths
+    fn relationships(&mut self, part: &str) -> Result<Vec<Relationship>, PackageError> {
+        let (dir, name) = part.rsplit_once('/').unwrap_or(("", part));
+        let rels_path = format!("{}/_rels/{}.rels", dir, name);
+        let Some(xml) = self.read_part(&rels_path)? else {
+            return Ok(vec![]);
+        };
+
+        let mut ret: Vec<Relationship> = vec![];
+        let mut reader = Reader::from_str(&xml);
+        loop {
+            match reader.read_event().map_err(PackageError::Xml)? {
+                Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"Relationship" => {
+                    let external = attribute(&e, b"TargetMode").map_err(PackageError::Xml)?;
+                    if external.as_deref() == Some("External") {
+                        continue;
+                    }
+                    if let (Some(id), Some(rel_type), Some(target)) = (
+                        attribute(&e, b"Id").map_err(PackageError::Xml)?,
+                        attribute(&e, b"Type").map_err(PackageError::Xml)?,
+                        attribute(&e, b"Target").map_err(PackageError::Xml)?,
+                    ) {
+                        ret.push(Relationship {
+                            id,
+                            rel_type,
+                            target: resolve_path(dir, &target),
+                        });
+                    }
+                }
+                Event::Eof => break,
+                _ => (),
+            }
+        }
+        Ok(ret)
+    }
+
+    /// part content as text. `None` when missing
+    fn read_part(&mut self, path: &str) -> Result<Option<String>, PackageError> {
+        let mut file = match self.archive.by_name(path) {
+            Ok(file) => file,
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(PackageError::Zip(err)),
+        };
+        let mut ret = String::new();
+        file.read_to_string(&mut ret).map_err(PackageError::Io)?;
+        Ok(Some(ret))
+    }
+}
+
+/// legacy comments (notes) by 0-based (row, col) as `author: text`
+fn parse_comments(xml: &str) -> Result<HashMap<(u32, u32), String>, quick_xml::Error> {
+    let mut authors: Vec<String> = vec![];
+    let mut ret: HashMap<(u32, u32), String> = HashMap::new();
+    // (pos, author id, text) of comment being read
+    let mut comment: Option<((u32, u32), usize, String)> = None;
+    let mut in_author = false;
+    let mut in_text = false;
+    // phonetic text is not part of comment
+    let mut in_phonetic = false;
+
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"author" => {
+                    in_author = true;
+                    authors.push(String::new());
+                }
+                b"comment" => {
+                    let pos = attribute(&e, b"ref")?.and_then(|x| cell_pos(&x));
+                    let author_id = attribute(&e, b"authorId")?
+                        .and_then(|x| x.parse().ok())
+                        .unwrap_or_default();
+                    comment = pos.map(|pos| (pos, author_id, String::new()));
+                }
+                b"t" => in_text = true,
+                b"rPh" => in_phonetic = true,
+                _ => (),
+            },
+            Event::Text(e) => {
+                if in_author {
+                    if let Some(author) = authors.last_mut() {
+                        author.push_str(&e.unescape()?);
+                    }
+                } else if let Some((_, _, comment_text)) = comment.as_mut() {
+                    if in_text && !in_phonetic {
+                        comment_text.push_str(&e.unescape()?);
+                    }
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"author" => in_author = false,
+                b"comment" => {
+                    if let Some((pos, author_id, text)) = comment.take() {
+                        let author = authors.get(author_id).map_or("", |x| x.as_str());
+                        // excel starts note text with `author:` in bold
+                        let text = text
+                            .strip_prefix(author)
+                            .and_then(|x| x.strip_prefix(':'))
+                            .map_or(text.as_str(), |x| x.trim_start());
+                        ret.insert(pos, format!("{}: {}", author, text));
+                    }
+                }
+                b"t" => in_text = false,
+                b"rPh" => in_phonetic = false,
+                _ => (),
+            },
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(ret)
+}
+
+/// table part. `None` when it has no valid ref
+fn parse_table(xml: &str, sheet: &str) -> Result<Option<Table>, quick_xml::Error> {
+    let mut table: Option<Table> = None;
+
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"table" => {
+                let name = match attribute(&e, b"displayName")? {
+                    Some(name) => name,
+                    None => attribute(&e, b"name")?.unwrap_or_default(),
+                };
+                let range = attribute(&e, b"ref")?.and_then(|x| x.parse::<CellRange>().ok());
+                let id = attribute(&e, b"id")?.unwrap_or_default();
+                let count = |x: Option<String>, default: usize| {
+                    x.and_then(|x| x.parse::<usize>().ok()).unwrap_or(default)
+                };
+                let header_row_count = count(attribute(&e, b"headerRowCount")?, 1);
+                let totals_row_count = count(attribute(&e, b"totalsRowCount")?, 0);
+                table = range.map(|range| Table {
+                    id,
+                    name,
+                    sheet: sheet.to_owned(),
+                    range,
+                    header_row_count,
+                    totals_row_count,
+                    columns: vec![],
+                });
+            }
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"tableColumn" => {
+                if let Some(table) = table.as_mut() {
+                    let id = attribute(&e, b"id")?.unwrap_or_default();
+                    let name = attribute(&e, b"name")?.unwrap_or_default();
+                    table.columns.push((id, name));
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(table)
+}
+
+/// threaded comments in order of appearance
+fn parse_threaded_comments(xml: &str) -> Result<Vec<ThreadedComment>, quick_xml::Error> {
+    let mut ret: Vec<ThreadedComment> = vec![];
+    let mut comment: Option<ThreadedComment> = None;
+    let mut in_text = false;
+
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.local_name().as_ref() == b"threadedComment" => {
+                let pos = attribute(&e, b"ref")?.and_then(|x| cell_pos(&x));
+                let id = attribute(&e, b"id")?.unwrap_or_default();
+                comment = pos.map(|pos| ThreadedComment {
+                    pos,
+                    id,
+                    parent_id: None,
+                    person_id: String::new(),
+                    text: String::new(),
+                    done: false,
+                });
+                if let Some(comment) = comment.as_mut() {
+                    comment.parent_id = attribute(&e, b"parentId")?;
+                    comment.person_id = attribute(&e, b"personId")?.unwrap_or_default();
+                    comment.done = attribute(&e, b"done")?.is_some_and(|x| x == "1" || x == "true");
+                }
+            }
+            Event::Start(e) if e.local_name().as_ref() == b"text" => in_text = true,
+            Event::Text(e) => {
+                if let Some(comment) = comment.as_mut().filter(|_| in_text) {
+                    comment.text.push_str(&e.unescape()?);
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"text" => in_text = false,
+                b"threadedComment" => ret.extend(comment.take()),
+                _ => (),
+            },
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(ret)
+}
+
+/// cell styles in order of `cellXfs`, which cells refer to by index
+fn parse_styles(xml: &str) -> Result<Vec<CellStyle>, quick_xml::Error> {
+    let mut number_formats: HashMap<usize, String> = HashMap::new();
+    let mut fonts: Vec<Font> = vec![];
+    let mut fills: Vec<String> = vec![];
+    let mut borders: Vec<[String; 4]> = vec![];
+    let mut formats: Vec<CellFormat> = vec![];
+    // fonts, fills and so on appear in differential formats as well
+    let mut section: Option<Vec<u8>> = None;
+    let mut border_side: Option<usize> = None;
+
+    let mut reader = Reader::from_str(xml);
+    loop {
+        let (e, is_empty) = match reader.read_event()? {
+            Event::Start(e) => (e, false),
+            Event::Empty(e) => (e, true),
+            Event::End(e) => {
+                let name = e.local_name();
+                if section.as_deref() == Some(name.as_ref()) {
+                    section = None;
+                }
+                if matches!(name.as_ref(), b"left" | b"right" | b"top" | b"bottom") {
+                    border_side = None;
+                }
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        let name = e.local_name();
+        let name = name.as_ref();
+        match (section.as_deref(), name) {
+            (None, b"numFmts" | b"fonts" | b"fills" | b"borders" | b"cellXfs") if !is_empty => {
+                section = Some(name.to_vec());
+            }
+            (Some(b"numFmts"), b"numFmt") => {
+                let id = attribute(&e, b"numFmtId")?.and_then(|x| x.parse().ok());
+                if let (Some(id), Some(code)) = (id, attribute(&e, b"formatCode")?) {
+                    number_formats.insert(id, code);
+                }
+            }
+            (Some(b"fonts"), b"font") => fonts.push(Font::default()),
+            (Some(b"fonts"), _) => {
+                let Some(font) = fonts.last_mut() else {
+                    continue;
+                };
+                match name {
+                    b"name" => font.name = attribute(&e, b"val")?.unwrap_or_default(),
+                    b"sz" => font.size = attribute(&e, b"val")?.unwrap_or_default(),
+                    b"b" => font.bold = is_on(&e)?,
+                    b"i" => font.italic = is_on(&e)?,
+                    b"strike" => font.strike = is_on(&e)?,
+                    b"u" => font.underline = attribute(&e, b"val")?.unwrap_or("single".to_owned()),
+                    b"color" => font.color = color_text(&e)?,
+                    _ => (),
+                }
+            }
+            (Some(b"fills"), b"fill") => fills.push(String::new()),
+            (Some(b"fills"), b"patternFill") => {
+                if let Some(fill) = fills.last_mut() {
+                    *fill = attribute(&e, b"patternType")?.unwrap_or_default();
+                }
+            }
+            (Some(b"fills"), b"gradientFill") => {
+                if let Some(fill) = fills.last_mut() {
+                    *fill = "gradient".to_owned();
+                }
+            }
+            (Some(b"fills"), b"fgColor") => {
+                if let Some(fill) = fills.last_mut().filter(|x| !x.is_empty() && *x != "none") {
+                    fill.push(' ');
+                    fill.push_str(&color_text(&e)?);
+                }
+            }
+            (Some(b"borders"), b"border") => borders.push(Default::default()),
+            (Some(b"borders"), b"left" | b"right" | b"top" | b"bottom") => {
+                let side = match name {
+                    b"left" => 0,
+                    b"right" => 1,
+                    b"top" => 2,
+                    _ => 3,
+                };
+                if let Some(border) = borders.last_mut() {
+                    border[side] = attribute(&e, b"style")?.unwrap_or_default();
+                }
+                border_side = (!is_empty).then_some(side);
+            }
+            (Some(b"borders"), b"color") => {
+                let border = borders.last_mut();
+                if let Some(text) = border.zip(border_side).map(|(x, side)| &mut x[side]) {
+                    if !text.is_empty() && text != "none" {
+                        text.push(' ');
+                        text.push_str(&color_text(&e)?);
+                    }
+                }
+            }
+            (Some(b"cellXfs"), b"xf") => {
+                let id = |value: Option<String>| value.and_then(|x| x.parse().ok());
+                formats.push(CellFormat {
+                    number_format_id: id(attribute(&e, b"numFmtId")?).unwrap_or_default(),
+                    font_id: id(attribute(&e, b"fontId")?).unwrap_or_default(),
+                    fill_id: id(attribute(&e, b"fillId")?).unwrap_or_default(),
+                    border_id: id(attribute(&e, b"borderId")?).unwrap_or_default(),
+                    alignment: TextAlignment::default(),
+                });
+            }
+            (Some(b"cellXfs"), b"alignment") => {
+                if let Some(format) = formats.last_mut() {
+                    format.alignment = TextAlignment {
+                        horizontal: attribute(&e, b"horizontal")?.unwrap_or_default(),
+                        vertical: attribute(&e, b"vertical")?.unwrap_or_default(),
+                        wrap: attribute(&e, b"wrapText")?.is_some_and(|x| x == "1" || x == "true"),
+                        indent: attribute(&e, b"indent")?.unwrap_or_default(),
+                        rotation: attribute(&e, b"textRotation")?.unwrap_or_default(),
+                    };
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(formats
+        .into_iter()
+        .map(|x| CellStyle {
+            number_format: number_formats
+                .get(&x.number_format_id)
+                .cloned()
+                .unwrap_or_else(|| builtin_number_format(x.number_format_id)),
+            font: fonts.get(x.font_id).cloned().unwrap_or_default(),
+            fill: fills.get(x.fill_id).cloned().unwrap_or_default(),
+            borders: borders.get(x.border_id).cloned().unwrap_or_default(),
+            alignment: x.alignment,
+        })
+        .collect())
+}
+
+/// style index by 0-based (row, col) of cells with `s` attribute
+fn parse_cell_style_ids(xml: &str) -> Result<HashMap<(u32, u32), usize>, quick_xml::Error> {
+    let mut ret: HashMap<(u32, u32), usize> = HashMap::new();
+    // cells and rows may omit their positions, which follow the previous ones then.
+    // `None` while in row with invalid position such as `0`, whose cells are skipped
+    let mut row: Option<u32> = Some(0);
+    let mut next_row: u32 = 0;
+    let mut next_col: u32 = 0;
+
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"row" => {
+                row = match attribute(&e, b"r")? {
+                    Some(x) => x.parse::<u32>().ok().and_then(|x| x.checked_sub(1)),
+                    None => Some(next_row),
+                };
+                next_row = row.map_or(next_row, |x| x + 1);
+                next_col = 0;
+            }
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"c" => {
+                let pos = match attribute(&e, b"r")? {
+                    Some(x) => cell_pos(&x),
+                    None => row.map(|row| (row, next_col)),
+                };
+                // such as `A0`
+                let Some(pos) = pos else {
+                    continue;
+                };
+                next_col = pos.1 + 1;
+                if let Some(id) = attribute(&e, b"s")?.and_then(|x| x.parse().ok()) {
+                    ret.insert(pos, id);
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(ret)
+}
+
+/// format code of built-in number format id
+fn builtin_number_format(id: usize) -> String {
+    let code = match id {
+        0 => "General",
+        1 => "0",
+        2 => "0.00",
+        3 => "#,##0",
+        4 => "#,##0.00",
+        9 => "0%",
+        10 => "0.00%",
+        11 => "0.00E+00",
+        12 => "# ?/?",
+        13 => "# ??/??",
+        14 => "mm-dd-yy",
+        15 => "d-mmm-yy",
+        16 => "d-mmm",
+        17 => "mmm-yy",
+        18 => "h:mm AM/PM",
+        19 => "h:mm:ss AM/PM",
+        20 => "h:mm",
+        21 => "h:mm:ss",
+        22 => "m/d/yy h:mm",
+        37 => "#,##0 ;(#,##0)",
+        38 => "#,##0 ;[Red](#,##0)",
+        39 => "#,##0.00;(#,##0.00)",
+        40 => "#,##0.00;[Red](#,##0.00)",
+        45 => "mm:ss",
+        46 => "[h]:mm:ss",
+        47 => "mmss.0",
+        48 => "##0.0E+0",
+        49 => "@",
+        id => return format!("built-in {}", id),
+    };
+    code.to_owned()
+}
+
+/// color such as `#FF0000`, `theme 1`, `indexed 64` or `auto`, with tint if any
+fn color_text(e: &BytesStart) -> Result<String, quick_xml::Error> {
+    let mut ret = if let Some(rgb) = attribute(e, b"rgb")? {
+        // drop alpha of ARGB
+        let rgb = if rgb.len() == 8 { &rgb[2..] } else { &rgb };
+        format!("#{}", rgb)
+    } else if let Some(theme) = attribute(e, b"theme")? {
+        format!("theme {}", theme)
+    } else if let Some(indexed) = attribute(e, b"indexed")? {
+        format!("indexed {}", indexed)
+    } else {
+        "auto".to_owned()
+    };
+    if let Some(tint) = attribute(e, b"tint")? {
+        ret.push_str(&format!(" tint {}", tint));
+    }
+    Ok(ret)
+}
+
+/// boolean element such as `<b/>`, which is on unless `val` is false
+fn is_on(e: &BytesStart) -> Result<bool, quick_xml::Error> {
+    Ok(attribute(e, b"val")?.map_or(true, |x| x != "0" && x != "false"))
+}
+
+/// attribute value unescaped
+fn attribute(e: &BytesStart, name: &[u8]) -> Result<Option<String>, quick_xml::Error> {
+    match e.try_get_attribute(name)? {
+        Some(attr) => attribute_value(&attr).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// `r:id` attribute whose namespace prefix may vary
+fn relationship_id(e: &BytesStart) -> Result<Option<String>, quick_xml::Error> {
+    for attr in e.attributes() {
+        let attr = attr?;
+        if attr.key.prefix().is_some() && attr.key.local_name().as_ref() == b"id" {
+            return attribute_value(&attr).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// attribute value unescaped. parts are read as utf-8
+fn attribute_value(attr: &Attribute) -> Result<String, quick_xml::Error> {
+    let value = std::str::from_utf8(&attr.value)?;
+    Ok(unescape(value)?.to_string())
+}
+
+/// 0-based (row, col) of cell address
+fn cell_pos(address: &str) -> Option<(u32, u32)> {
+    let (row, col) = address_to_cell_pos(address)?;
+    Some((row.checked_sub(1)? as u32, col.checked_sub(1)? as u32))
+}
+
+/// resolve relationship target relative to dir of source part
+fn resolve_path(dir: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_owned();
+    }
+    let mut segments: Vec<&str> = dir.split('/').filter(|x| !x.is_empty()).collect();
+    for segment in target.split('/') {
+        match segment {
+            ".." => {
+                segments.pop();
+            }
+            "." | "" => (),
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
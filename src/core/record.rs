@@ -0,0 +1,118 @@
+use std::{collections::HashMap, ops};
+
+use calamine::{Data, Range};
+
+use super::{address::col_to_letters, options::KeyColumn, utils::data_to_string};
+
+/// sheet read as records: the first row is header and the following rows are records
+pub struct Records {
+    /// 0-based col and its field name: header text, or col letters when header is empty
+    pub fields: Vec<(u32, String)>,
+    /// key and 0-based row of each record in sheet order
+    pub keyed: Vec<(String, u32)>,
+    /// 0-based rows whose key is empty while the others are not
+    pub unkeyed: Vec<u32>,
+}
+
+impl Records {
+    /// read records keyed by key cols. returns key not found as error
+    pub fn read(range: &Range<Data>, keys: &[KeyColumn]) -> Result<Self, KeyColumn> {
+        let (Some((start_row, start_col)), Some((end_row, end_col))) = (range.start(), range.end())
+        else {
+            return Ok(Records {
+                fields: vec![],
+                keyed: vec![],
+                unkeyed: vec![],
+            });
+        };
+
+        let fields: Vec<(u32, String)> = (start_col..=end_col)
+            .map(|col| {
+                let header = cell_text(range, start_row, col);
+                if header.is_empty() {
+                    (col, col_to_letters((col + 1) as usize))
+                } else {
+                    (col, header)
+                }
+            })
+            .collect();
+
+        Self::read_rows(range, fields, start_row + 1..end_row + 1, keys)
+    }
+
+    /// read records keyed by key cols with fields given, where every row of range is record
+    /// such as excel table without header row. returns key not found as error
+    pub fn read_with_fields(
+        range: &Range<Data>,
+        fields: Vec<(u32, String)>,
+        keys: &[KeyColumn],
+    ) -> Result<Self, KeyColumn> {
+        let rows = match (range.start(), range.end()) {
+            (Some((start_row, _)), Some((end_row, _))) => start_row..end_row + 1,
+            _ => 0..0,
+        };
+        Self::read_rows(range, fields, rows, keys)
+    }
+
+    fn read_rows(
+        range: &Range<Data>,
+        fields: Vec<(u32, String)>,
+        rows: ops::Range<u32>,
+        keys: &[KeyColumn],
+    ) -> Result<Self, KeyColumn> {
+        let key_cols = keys
+            .iter()
+            .map(|key| match key {
+                KeyColumn::Col(col) if fields.iter().any(|(x, _)| x + 1 == *col as u32) => {
+                    Ok((col - 1) as u32)
+                }
+                KeyColumn::Col(_) => Err(key.clone()),
+                KeyColumn::Header(header) => fields
+                    .iter()
+                    .find(|(_, field)| field == header)
+                    .map(|(col, _)| *col)
+                    .ok_or_else(|| key.clone()),
+            })
+            .collect::<Result<Vec<u32>, KeyColumn>>()?;
+
+        let mut keyed: Vec<(String, u32)> = vec![];
+        let mut unkeyed: Vec<u32> = vec![];
+        for row in rows {
+            let values: Vec<String> = key_cols
+                .iter()
+                .map(|col| cell_text(range, row, *col))
+                .collect();
+            if !values.iter().all(|x| x.is_empty()) {
+                keyed.push((values.join(", "), row));
+            } else if fields
+                .iter()
+                .any(|(col, _)| !cell_text(range, row, *col).is_empty())
+            {
+                unkeyed.push(row);
+            }
+        }
+
+        Ok(Records {
+            fields,
+            keyed,
+            unkeyed,
+        })
+    }
+
+    /// rows of each key
+    pub fn rows_by_key(&self) -> HashMap<&str, Vec<u32>> {
+        let mut ret: HashMap<&str, Vec<u32>> = HashMap::new();
+        for (key, row) in self.keyed.iter() {
+            ret.entry(key.as_str()).or_default().push(*row);
+        }
+        ret
+    }
+}
+
+/// cell text, empty when cell is empty
+pub fn cell_text(range: &Range<Data>, row: u32, col: u32) -> String {
+    range
+        .get_value((row, col))
+        .map(data_to_string)
+        .unwrap_or_default()
+}
@@ -3,7 +3,13 @@ use std::fmt;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use super::diff::Diff;
+use super::{
+    address::{cell_pos_to_address, col_to_letters, CellRange},
+    diff::{
+        CellDiff, CellDiffKind, CellType, CellValue, CombinedCellDiff, Diff, DuplicateKey,
+        RecordDiff, RecordDiffKind, TableDiffKind,
+    },
+};
 
 /// unified diff
 #[derive(Clone, Debug)]
@@ -61,6 +67,7 @@ pub struct SplitUnifiedDiffLine {
     pub text: Option<String>,
 }
 
+#[allow(clippy::manual_map)]
 impl UnifiedDiff {
     /// convert each string to one in unified format
     pub fn format(&self) -> FormattedUnifiedDiff {
@@ -183,8 +190,31 @@ impl fmt::Display for FormattedUnifiedDiff {
     }
 }
 
+/// how value and formula diffs of the same cell are shown
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CellLayout {
+    /// value and formula in separate hunks
+    Split,
+    /// value and formula in one hunk
+    Combined,
+}
+
 /// get unified diff str split into old / new parts
+///
+/// value and formula diffs of the same cell are in separate hunks
 pub fn unified_diff(diff: &Diff) -> UnifiedDiff {
+    build_unified_diff(diff, CellLayout::Split)
+}
+
+/// get unified diff where value and formula diffs of the same cell are in one hunk
+/// such as `=1+1 (2)` to `=2*4 (8)`
+pub fn unified_diff_combined(diff: &Diff) -> UnifiedDiff {
+    build_unified_diff(diff, CellLayout::Combined)
+}
+
+/// get unified diff in layout
+#[allow(clippy::manual_map)]
+fn build_unified_diff(diff: &Diff, layout: CellLayout) -> UnifiedDiff {
     let mut ret: Vec<UnifiedDiffContent> = vec![];
 
     if !diff.sheet_diff.is_empty() {
@@ -205,8 +235,21 @@ pub fn unified_diff(diff: &Diff) -> UnifiedDiff {
                 } else {
                     None
                 };
+                let mut changes: Vec<String> = vec![];
+                if let Some(similarity) = x.similarity {
+                    changes.push(format!("renamed ({:.0}% similar)", similarity * 100.0));
+                }
+                if let (Some(old_position), Some(new_position)) = (x.old_position, x.new_position) {
+                    changes.push(format!("moved {} -> {}", old_position, new_position));
+                }
+                if let (Some(old_visibility), Some(new_visibility)) =
+                    (x.old_visibility, x.new_visibility)
+                {
+                    changes.push(format!("{} -> {}", old_visibility, new_visibility));
+                }
+                let pos = (!changes.is_empty()).then(|| format!("sheet {}", changes.join(", ")));
                 UnifiedDiffLine {
-                    pos: None,
+                    pos,
                     old: old_sheet,
                     new: new_sheet,
                 }
@@ -220,35 +263,197 @@ pub fn unified_diff(diff: &Diff) -> UnifiedDiff {
         });
     }
 
+    if !diff.defined_names.is_empty() {
+        let old_title = format!("{} (defined names)", diff.old_filepath);
+        let new_title = format!("{} (defined names)", diff.new_filepath);
+
+        let lines: Vec<UnifiedDiffLine> = diff
+            .defined_names
+            .iter()
+            .map(|x| {
+                // sheet-scoped name is qualified as excel shows in formulas
+                let name = match &x.scope {
+                    Some(scope) => format!("{}!{}", quote_sheet_name(scope), x.name),
+                    None => x.name.to_owned(),
+                };
+                UnifiedDiffLine {
+                    pos: Some(format!("{} {}", name, x.kind)),
+                    old: x.old.clone(),
+                    new: x.new.clone(),
+                }
+            })
+            .collect();
+
+        ret.push(UnifiedDiffContent {
+            old_title,
+            new_title,
+            lines,
+        });
+    }
+
+    if !diff.tables.is_empty() {
+        let old_title = format!("{} (tables)", diff.old_filepath);
+        let new_title = format!("{} (tables)", diff.new_filepath);
+
+        let lines: Vec<UnifiedDiffLine> = diff
+            .tables
+            .iter()
+            .flat_map(|x| {
+                let name = x.new_name.as_ref().or(x.old_name.as_ref());
+                let name = name.map_or("", |x| x.as_str());
+                let table_text =
+                    |name: &Option<String>, sheet: &Option<String>, range: &Option<CellRange>| {
+                        name.as_ref().zip(sheet.as_ref()).zip(range.as_ref()).map(
+                            |((name, sheet), range)| {
+                                format!("{} ({}!{})", name, quote_sheet_name(sheet), range)
+                            },
+                        )
+                    };
+                let old = table_text(&x.old_name, &x.old_sheet, &x.old_range);
+                let new = table_text(&x.new_name, &x.new_sheet, &x.new_range);
+
+                let mut changes: Vec<String> = vec![];
+                if x.is_renamed() {
+                    changes.push("renamed".to_owned());
+                }
+                if x.is_resized() {
+                    changes.push("resized".to_owned());
+                }
+                if x.is_moved() {
+                    changes.push("moved".to_owned());
+                }
+                let table_line = match x.kind {
+                    TableDiffKind::Changed if changes.is_empty() => None,
+                    TableDiffKind::Changed => Some(UnifiedDiffLine {
+                        pos: Some(format!("{} table {}", name, changes.join(", "))),
+                        old,
+                        new,
+                    }),
+                    _ => Some(UnifiedDiffLine {
+                        pos: Some(format!("{} table {}", name, x.kind)),
+                        old,
+                        new,
+                    }),
+                };
+
+                let column_lines = x.columns.iter().map(|column| {
+                    let column_name = column.new.as_ref().or(column.old.as_ref());
+                    UnifiedDiffLine {
+                        pos: Some(format!(
+                            "{}[{}] column {}",
+                            name,
+                            column_name.map_or("", |x| x.as_str()),
+                            column.kind
+                        )),
+                        old: column.old.clone(),
+                        new: column.new.clone(),
+                    }
+                });
+
+                table_line
+                    .into_iter()
+                    .chain(column_lines)
+                    .chain(record_diff_lines(
+                        &x.records,
+                        &x.duplicate_keys,
+                        &format!("{} ", name),
+                    ))
+                    .collect::<Vec<UnifiedDiffLine>>()
+            })
+            .collect();
+
+        ret.push(UnifiedDiffContent {
+            old_title,
+            new_title,
+            lines,
+        });
+    }
+
     let cell_diffs_content: Vec<UnifiedDiffContent> = diff
         .cell_diffs
         .iter()
         .map(|x| {
-            let cell_diffs_lines: Vec<UnifiedDiffLine> = x
-                .cells
-                .iter()
-                .map(|x| {
-                    let pos = Some(format!("{}({},{}) {}", x.addr, x.row, x.col, x.kind));
+            let column_diffs_lines = x.columns.iter().map(|x| {
+                let col_pos = |col: usize| {
+                    let letters = col_to_letters(col);
+                    format!("{}:{}", letters, letters)
+                };
+                let pos = match (x.old_col, x.new_col) {
+                    (Some(old_col), Some(new_col)) => format!(
+                        "{} -> {} column {}",
+                        col_pos(old_col),
+                        col_pos(new_col),
+                        x.kind
+                    ),
+                    (old_col, new_col) => format!(
+                        "{} column {}",
+                        col_pos(old_col.or(new_col).unwrap_or(1)),
+                        x.kind
+                    ),
+                };
 
-                    let old = if let Some(sheet) = x.old.as_ref() {
-                        Some(sheet.to_owned())
-                    } else {
-                        None
-                    };
-                    let new = if let Some(sheet) = x.new.as_ref() {
-                        Some(sheet.to_owned())
-                    } else {
-                        None
-                    };
+                let old = x.old.clone();
+                let new = x.new.clone();
 
-                    UnifiedDiffLine { pos, old, new }
-                })
-                .collect();
+                UnifiedDiffLine {
+                    pos: Some(pos),
+                    old,
+                    new,
+                }
+            });
+
+            let row_diffs_lines = x.rows.iter().map(|x| {
+                let row = x.old_row.or(x.new_row).unwrap_or_default();
+                let kind = if x.old_row.is_some() {
+                    "deleted"
+                } else {
+                    "inserted"
+                };
+                let pos = Some(format!("{}:{} row {}", row, row, kind));
+
+                let old = x.old.clone();
+                let new = x.new.clone();
+
+                UnifiedDiffLine { pos, old, new }
+            });
+
+            let cell_diffs_lines: Vec<UnifiedDiffLine> = match layout {
+                CellLayout::Split => x.cells.iter().map(cell_diff_line).collect(),
+                CellLayout::Combined => x
+                    .combined_cells()
+                    .iter()
+                    .map(combined_cell_diff_line)
+                    .collect(),
+            };
+
+            let record_diffs_lines = record_diff_lines(&x.records, &x.duplicate_keys, "");
+
+            let merged_cells_lines = x.merged_cells.iter().map(|x| {
+                let pos = x.old.or(x.new).map(|range| {
+                    let addr = cell_pos_to_address(range.start_row, range.start_col);
+                    format!("{} merge {}", addr, x.kind)
+                });
+
+                UnifiedDiffLine {
+                    pos,
+                    old: x.old.map(|x| x.to_string()),
+                    new: x.new.map(|x| x.to_string()),
+                }
+            });
 
             UnifiedDiffContent {
                 old_title: format!("{} [{}]", diff.old_filepath, x.sheet),
-                new_title: format!("{} [{}]", diff.new_filepath, x.sheet),
-                lines: cell_diffs_lines,
+                new_title: format!(
+                    "{} [{}]",
+                    diff.new_filepath,
+                    x.new_sheet.as_ref().unwrap_or(&x.sheet)
+                ),
+                lines: column_diffs_lines
+                    .chain(row_diffs_lines)
+                    .chain(cell_diffs_lines)
+                    .chain(record_diffs_lines)
+                    .chain(merged_cells_lines)
+                    .collect(),
             }
         })
         .collect();
@@ -257,3 +462,163 @@ pub fn unified_diff(diff: &Diff) -> UnifiedDiff {
 
     UnifiedDiff { content: ret }
 }
+
+/// cell position such as `D10(10,4)`, or `D10(10,4) -> D11(11,4)` when moved
+fn cell_pos(addr: &str, row: usize, col: usize, new: (&str, usize, usize)) -> String {
+    let (new_addr, new_row, new_col) = new;
+    if addr == new_addr {
+        format!("{}({},{})", addr, row, col)
+    } else {
+        format!(
+            "{}({},{}) -> {}({},{})",
+            addr, row, col, new_addr, new_row, new_col
+        )
+    }
+}
+
+/// record diff lines such as `[key] field changed`, followed by duplicate key lines.
+/// `prefix` is put before key such as table name
+fn record_diff_lines(
+    records: &[RecordDiff],
+    duplicate_keys: &[DuplicateKey],
+    prefix: &str,
+) -> Vec<UnifiedDiffLine> {
+    let record_diffs_lines = records.iter().flat_map(|x| {
+        let row = x.old_row.or(x.new_row).unwrap_or_default();
+        match x.kind {
+            RecordDiffKind::Changed => x
+                .fields
+                .iter()
+                .map(|field| UnifiedDiffLine {
+                    pos: Some(format!("{}[{}] {} changed", prefix, x.key, field.field)),
+                    old: field.old.clone(),
+                    new: field.new.clone(),
+                })
+                .collect::<Vec<UnifiedDiffLine>>(),
+            _ => vec![UnifiedDiffLine {
+                pos: Some(format!(
+                    "{}[{}] {}:{} record {}",
+                    prefix, x.key, row, row, x.kind
+                )),
+                old: x.old.clone(),
+                new: x.new.clone(),
+            }],
+        }
+    });
+
+    let duplicate_keys_lines = duplicate_keys.iter().map(|x| {
+        let rows_text = |rows: &[usize]| {
+            (!rows.is_empty()).then(|| {
+                let rows: Vec<String> = rows.iter().map(|x| x.to_string()).collect();
+                format!("rows {}", rows.join(", "))
+            })
+        };
+
+        UnifiedDiffLine {
+            pos: Some(format!("{}[{}] duplicate key", prefix, x.key)),
+            old: rows_text(&x.old_rows),
+            new: rows_text(&x.new_rows),
+        }
+    });
+
+    record_diffs_lines.chain(duplicate_keys_lines).collect()
+}
+
+/// quote sheet name such as `'My Sheet'` unless it consists of word chars only
+fn quote_sheet_name(sheet: &str) -> String {
+    if sheet.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        sheet.to_owned()
+    } else {
+        format!("'{}'", sheet.replace('\'', "''"))
+    }
+}
+
+/// one hunk of value or formula diff
+fn cell_diff_line(x: &CellDiff) -> UnifiedDiffLine {
+    let pos = cell_pos(&x.addr, x.row, x.col, (&x.new_addr, x.new_row, x.new_col));
+    let pos = Some(format!("{} {}", pos, x.kind));
+
+    let (old, new) = value_texts(x);
+
+    UnifiedDiffLine { pos, old, new }
+}
+
+/// one hunk of value and formula diffs such as `=1+1 (2)`, followed by comment such as
+/// `// author: text` if changed too
+fn combined_cell_diff_line(x: &CombinedCellDiff) -> UnifiedDiffLine {
+    let pos = cell_pos(&x.addr, x.row, x.col, (&x.new_addr, x.new_row, x.new_col));
+    let pos = match &x.comment {
+        Some(_) if x.kind != CellDiffKind::Comment => format!("{} {}, comment", pos, x.kind),
+        _ => format!("{} {}", pos, x.kind),
+    };
+    let pos = Some(pos);
+
+    // constant unchanged by formula to / from constant is in formula diff
+    let (old_value, new_value) = match (&x.value, &x.formula) {
+        (Some(value), _) => value_texts(value),
+        (None, Some(formula)) => (
+            formula.old_value.as_ref().map(|x| x.to_string()),
+            formula.new_value.as_ref().map(|x| x.to_string()),
+        ),
+        (None, None) => (None, None),
+    };
+    let (old_formula, new_formula) = x
+        .formula
+        .as_ref()
+        .map(|x| (x.old.clone(), x.new.clone()))
+        .unwrap_or_default();
+    let combine = |formula: Option<String>, value: Option<String>| match (formula, value) {
+        (Some(formula), Some(value)) => Some(format!("={} ({})", formula, value)),
+        (Some(formula), None) => Some(format!("={}", formula)),
+        (None, value) => value,
+    };
+    let old = combine(old_formula, old_value);
+    let new = combine(new_formula, new_value);
+
+    let (old, new) = match &x.comment {
+        Some(comment) if x.kind == CellDiffKind::Comment => {
+            (comment.old.clone(), comment.new.clone())
+        }
+        Some(comment) => {
+            let annotate = |text: Option<String>, comment: &Option<String>| match (text, comment) {
+                (Some(text), Some(comment)) => Some(format!("{} // {}", text, comment)),
+                (None, Some(comment)) => Some(format!("// {}", comment)),
+                (text, None) => text,
+            };
+            (annotate(old, &comment.old), annotate(new, &comment.new))
+        }
+        None => (old, new),
+    };
+
+    UnifiedDiffLine { pos, old, new }
+}
+
+/// old / new value texts. type change only, as type or result diff, shows types as text is
+/// the same, and formula to / from constant shows formula with `=` and constant value
+fn value_texts(x: &CellDiff) -> (Option<String>, Option<String>) {
+    let formula = |text: &Option<String>| text.as_ref().map(|x| format!("={}", x));
+    let constant = |value: &Option<CellValue>| value.as_ref().map(|x| x.to_string());
+    match x.kind {
+        CellDiffKind::FormulaToConstant => return (formula(&x.old), constant(&x.new_value)),
+        CellDiffKind::ConstantToFormula => return (constant(&x.old_value), formula(&x.new)),
+        _ => (),
+    }
+
+    let is_type_only = match x.kind {
+        CellDiffKind::Type => true,
+        CellDiffKind::Result => x.old == x.new,
+        _ => false,
+    };
+    let with_type = |text: &Option<String>, cell_type: &Option<CellType>| match cell_type {
+        Some(cell_type) if is_type_only => Some(format!(
+            "{} {}",
+            cell_type,
+            text.as_deref().unwrap_or_default()
+        )),
+        _ => text.clone(),
+    };
+    (
+        with_type(&x.old, &x.old_type),
+        with_type(&x.new, &x.new_type),
+    )
+}
@@ -1,8 +1,10 @@
+use calamine::{CellType, Data, ExcelDateTime, Range};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+use super::{address::CellRange, diff::CellValue};
+
 /// filter sheets whose name is equal
-pub fn filter_same_name_sheets<'a>(
-    old_sheets: &'a Vec<String>,
-    new_sheets: &'a Vec<String>,
-) -> Vec<String> {
+pub fn filter_same_name_sheets(old_sheets: &[String], new_sheets: &[String]) -> Vec<String> {
     old_sheets
         .iter()
         .filter(|s| new_sheets.contains(s))
@@ -12,7 +14,7 @@ pub fn filter_same_name_sheets<'a>(
 
 /// get range to compare
 /// return: (start_row, start_col, end_row, end_col)
-pub fn diff_range<'a>(
+pub fn diff_range(
     old_start: Option<(u32, u32)>,
     new_start: Option<(u32, u32)>,
     old_end: Option<(u32, u32)>,
@@ -42,18 +44,188 @@ pub fn diff_range<'a>(
     (start_row, start_col, end_row + 1, end_col + 1)
 }
 
-/// convert (row, col) to cell address str
-pub fn cell_pos_to_address(row: usize, col: usize) -> String {
-    let col_letter = (col as u8 - 1) / 26;
-    let col_index = (col as u8 - 1) % 26;
+/// get range covering all ranges
+/// return: (start_row, start_col, end_row, end_col)
+pub fn union_range(ranges: &[(u32, u32, u32, u32)]) -> (u32, u32, u32, u32) {
+    ranges.iter().fold(
+        (u32::MAX, u32::MAX, u32::MIN, u32::MIN),
+        |(start_row, start_col, end_row, end_col), x| {
+            (
+                start_row.min(x.0),
+                start_col.min(x.1),
+                end_row.max(x.2),
+                end_col.max(x.3),
+            )
+        },
+    )
+}
+
+/// crop range to cell ranges so that cells out of them are not scanned.
+/// cells between several cell ranges are blanked
+///
+/// returns cell range with row or col 0 as error since cell ranges are 1-based
+pub fn crop_range<T: CellType>(
+    range: &Range<T>,
+    cell_ranges: &[CellRange],
+) -> Result<Range<T>, CellRange> {
+    // range is 0-based
+    let zero_based = cell_ranges
+        .iter()
+        .map(|x| {
+            let (start_row, start_col) = (x.start_row.checked_sub(1), x.start_col.checked_sub(1));
+            let (end_row, end_col) = (x.end_row.checked_sub(1), x.end_col.checked_sub(1));
+            match (start_row, start_col, end_row, end_col) {
+                (Some(start_row), Some(start_col), Some(end_row), Some(end_col)) => Ok((
+                    start_row as u32,
+                    start_col as u32,
+                    end_row as u32,
+                    end_col as u32,
+                )),
+                _ => Err(*x),
+            }
+        })
+        .collect::<Result<Vec<(u32, u32, u32, u32)>, CellRange>>()?;
+
+    let (Some((start_row, start_col)), Some((end_row, end_col))) = (range.start(), range.end())
+    else {
+        return Ok(range.clone());
+    };
+    let start_row = zero_based
+        .iter()
+        .map(|x| x.0)
+        .min()
+        .map_or(start_row, |x| x.max(start_row));
+    let start_col = zero_based
+        .iter()
+        .map(|x| x.1)
+        .min()
+        .map_or(start_col, |x| x.max(start_col));
+    let end_row = zero_based
+        .iter()
+        .map(|x| x.2)
+        .max()
+        .map_or(end_row, |x| x.min(end_row));
+    let end_col = zero_based
+        .iter()
+        .map(|x| x.3)
+        .max()
+        .map_or(end_col, |x| x.min(end_col));
+    if end_row < start_row || end_col < start_col {
+        return Ok(Range::empty());
+    }
+
+    let mut ret = range.range((start_row, start_col), (end_row, end_col));
+    if 1 < cell_ranges.len() {
+        let outside: Vec<(u32, u32)> = ret
+            .used_cells()
+            .map(|(row, col, _)| (start_row + row as u32, start_col + col as u32))
+            .filter(|(row, col)| {
+                !cell_ranges
+                    .iter()
+                    .any(|x| x.contains(*row as usize + 1, *col as usize + 1))
+            })
+            .collect();
+        outside
+            .into_iter()
+            .for_each(|pos| ret.set_value(pos, T::default()));
+    }
+    Ok(ret)
+}
+
+/// cell text. excel dates, times and durations are in ISO 8601
+pub fn data_to_string(data: &Data) -> String {
+    match temporal_value(data) {
+        Some(value) => value.to_string(),
+        None => data.to_string(),
+    }
+}
+
+/// whether date time is time of day without date
+pub fn is_time_only(x: &ExcelDateTime) -> bool {
+    (0.0..1.0).contains(&x.as_f64())
+}
+
+/// typed date, time or duration of cell. `None` for the other values or those out of range
+pub fn temporal_value(data: &Data) -> Option<CellValue> {
+    match data {
+        Data::DateTime(x) if x.is_duration() => Some(CellValue::Duration(
+            (x.as_f64() * 86_400_000.0).round() as i64,
+        )),
+        // epoch is taken into account by calamine
+        Data::DateTime(x) => x.as_datetime().map(|datetime| {
+            if is_time_only(x) {
+                CellValue::Time(datetime.time())
+            } else {
+                CellValue::DateTime(datetime)
+            }
+        }),
+        Data::DateTimeIso(x) => NaiveDateTime::parse_from_str(x, "%Y-%m-%dT%H:%M:%S%.f")
+            .map(CellValue::DateTime)
+            .or_else(|_| {
+                NaiveDate::parse_from_str(x, "%Y-%m-%d")
+                    .map(|x| CellValue::DateTime(x.and_time(NaiveTime::MIN)))
+            })
+            .or_else(|_| NaiveTime::parse_from_str(x, "%H:%M:%S%.f").map(CellValue::Time))
+            .ok(),
+        Data::DurationIso(x) => iso_to_duration(x).map(CellValue::Duration),
+        _ => None,
+    }
+}
+
+/// milliseconds to ISO 8601 duration such as `PT36H30M`
+pub fn duration_to_iso(ms: i64) -> String {
+    let sign = if ms < 0 { "-" } else { "" };
+    let ms = ms.unsigned_abs();
+    let (hours, minutes, seconds, millis) = (
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1_000 % 60,
+        ms % 1_000,
+    );
+
+    let mut ret = format!("{}PT", sign);
+    if 0 < hours {
+        ret.push_str(&format!("{}H", hours));
+    }
+    if 0 < minutes {
+        ret.push_str(&format!("{}M", minutes));
+    }
+    if 0 < millis {
+        ret.push_str(&format!("{}.{:03}S", seconds, millis));
+    } else if 0 < seconds || ms == 0 {
+        ret.push_str(&format!("{}S", seconds));
+    }
+    ret
+}
 
-    let col_char = if col_letter == 0 {
-        ((b'A' + col_index) as char).to_string()
-    } else {
-        let first_char = (b'A' + col_letter - 1) as char;
-        let second_char = (b'A' + col_index) as char;
-        format!("{}{}", first_char, second_char)
+/// ISO 8601 duration such as `PT36H30M00S` or `P1DT2H` to milliseconds. years and months,
+/// whose lengths vary, are not supported
+fn iso_to_duration(text: &str) -> Option<i64> {
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(text) => (-1.0, text),
+        None => (1.0, text),
     };
+    let text = text.strip_prefix('P')?;
+    let (date, time) = text.split_once('T').unwrap_or((text, ""));
 
-    format!("{}{}", col_char, row)
+    let mut ms = 0.0;
+    for (part, units) in [
+        (date, &[('D', 86_400_000.0)][..]),
+        (
+            time,
+            &[('H', 3_600_000.0), ('M', 60_000.0), ('S', 1_000.0)][..],
+        ),
+    ] {
+        let mut rest = part;
+        for (unit, unit_ms) in units {
+            if let Some((number, after)) = rest.split_once(*unit) {
+                ms += number.parse::<f64>().ok()? * unit_ms;
+                rest = after;
+            }
+        }
+        if !rest.is_empty() {
+            return None;
+        }
+    }
+    Some((sign * ms).round() as i64)
 }
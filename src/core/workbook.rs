@@ -0,0 +1,419 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+    rc::Rc,
+};
+
+use calamine::{
+    open_workbook_from_rs, Data, Ods, Range, Reader, SheetVisible, Sheets, Xls, Xlsb, Xlsx,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use super::{
+    address::CellRange,
+    delimited::read_delimited,
+    error::Error,
+    options::DiffOptions,
+    package::{DefinedName, PackageError, SheetStyles, Table, XlsxPackage},
+};
+
+/// magic bytes of compound file binary (.xls)
+const CFB_SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+/// magic bytes of zip archive (.xlsx, .xlsb, .ods)
+const ZIP_SIGNATURE: [u8; 4] = [0x50, 0x4B, 0x03, 0x04];
+
+/// workbook file format
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum WorkbookFormat {
+    Xlsx,
+    Xlsb,
+    Xls,
+    Ods,
+    Csv,
+    Tsv,
+}
+
+impl fmt::Display for WorkbookFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorkbookFormat::Xlsx => write!(f, "xlsx"),
+            WorkbookFormat::Xlsb => write!(f, "xlsb"),
+            WorkbookFormat::Xls => write!(f, "xls"),
+            WorkbookFormat::Ods => write!(f, "ods"),
+            WorkbookFormat::Csv => write!(f, "csv"),
+            WorkbookFormat::Tsv => write!(f, "tsv"),
+        }
+    }
+}
+
+impl WorkbookFormat {
+    /// detect format from file extension
+    pub fn from_extension(filepath: &str) -> Option<Self> {
+        let extension = Path::new(filepath)
+            .extension()
+            .and_then(|x| x.to_str())
+            .map(|x| x.to_lowercase());
+        match extension.as_deref() {
+            Some("xlsx" | "xlsm" | "xlam") => Some(WorkbookFormat::Xlsx),
+            Some("xlsb") => Some(WorkbookFormat::Xlsb),
+            Some("xls" | "xla") => Some(WorkbookFormat::Xls),
+            Some("ods") => Some(WorkbookFormat::Ods),
+            Some("csv") => Some(WorkbookFormat::Csv),
+            Some("tsv" | "tab") => Some(WorkbookFormat::Tsv),
+            _ => None,
+        }
+    }
+
+    /// detect format from magic bytes and, for zip archives, their entries
+    ///
+    /// the reader is rewound to the start afterwards
+    pub fn from_magic_bytes<R: Read + Seek>(reader: &mut R) -> Option<Self> {
+        let mut signature = [0u8; 8];
+        let read = reader.read(&mut signature).ok();
+        let ret = match read {
+            Some(8) if signature == CFB_SIGNATURE => Some(WorkbookFormat::Xls),
+            Some(n) if n >= 4 && signature[..4] == ZIP_SIGNATURE => zip_format(reader),
+            _ => None,
+        };
+        reader.seek(SeekFrom::Start(0)).ok()?;
+        ret
+    }
+}
+
+/// detect format from entries in zip archive
+fn zip_format<R: Read + Seek>(reader: &mut R) -> Option<WorkbookFormat> {
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    let archive = ZipArchive::new(reader).ok()?;
+    let has_entry = |name: &str| archive.file_names().any(|x| x.eq_ignore_ascii_case(name));
+    if has_entry("xl/workbook.xml") {
+        Some(WorkbookFormat::Xlsx)
+    } else if has_entry("xl/workbook.bin") {
+        Some(WorkbookFormat::Xlsb)
+    } else if has_entry("content.xml") {
+        Some(WorkbookFormat::Ods)
+    } else {
+        None
+    }
+}
+
+/// sheet tab visibility
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SheetVisibility {
+    Visible,
+    Hidden,
+    /// hidden and cannot be shown from user interface. excel formats only
+    VeryHidden,
+}
+
+impl fmt::Display for SheetVisibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SheetVisibility::Visible => write!(f, "visible"),
+            SheetVisibility::Hidden => write!(f, "hidden"),
+            SheetVisibility::VeryHidden => write!(f, "very hidden"),
+        }
+    }
+}
+
+impl From<SheetVisible> for SheetVisibility {
+    fn from(visible: SheetVisible) -> Self {
+        match visible {
+            SheetVisible::Visible => SheetVisibility::Visible,
+            SheetVisible::Hidden => SheetVisibility::Hidden,
+            SheetVisible::VeryHidden => SheetVisibility::VeryHidden,
+        }
+    }
+}
+
+/// any source workbook can be read from
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// opened workbook, or csv / tsv file read as single sheet workbook
+pub(crate) struct Workbook<'a> {
+    sheets: WorkbookSheets<'a>,
+    /// sheet selected by `#` suffix of file path
+    selected: Option<String>,
+    /// xlsx package to read parts calamine does not, such as comments
+    package: Option<XlsxPackage>,
+}
+
+enum WorkbookSheets<'a> {
+    Sheets(Box<Sheets<Box<dyn ReadSeek + 'a>>>),
+    Delimited { sheet: String, range: Range<Data> },
+}
+
+impl<'a> Workbook<'a> {
+    /// open workbook whose format is detected by magic bytes, or by file extension as fallback
+    ///
+    /// `book.xlsx#Sheet1` selects one sheet in the workbook
+    pub fn open(filepath: &str, options: &DiffOptions) -> Result<Self, Error> {
+        let (path, selected) = split_sheet_selector(filepath);
+
+        let file = File::open(path).map_err(|err| Error::Open {
+            filepath: path.to_owned(),
+            reason: err.to_string(),
+        })?;
+
+        Self::read(BufReader::new(file), path, selected, options)
+    }
+
+    /// read workbook from reader. label is used as file path to detect format and report errors
+    ///
+    /// `upload.xlsx#Sheet1` as label selects one sheet in the workbook
+    pub fn from_reader<RS: Read + Seek + 'a>(
+        reader: RS,
+        label: &str,
+        options: &DiffOptions,
+    ) -> Result<Self, Error> {
+        let (label, selected) = match label.rsplit_once('#') {
+            Some((label, sheet)) if !sheet.is_empty() => (label, Some(sheet)),
+            _ => (label, None),
+        };
+
+        Self::read(reader, label, selected, options)
+    }
+
+    /// read workbook and select sheet
+    fn read<RS: Read + Seek + 'a>(
+        mut reader: RS,
+        path: &str,
+        selected: Option<&str>,
+        options: &DiffOptions,
+    ) -> Result<Self, Error> {
+        let format = WorkbookFormat::from_magic_bytes(&mut reader)
+            .or_else(|| WorkbookFormat::from_extension(path))
+            .ok_or_else(|| Error::UnsupportedFormat {
+                filepath: path.to_owned(),
+            })?;
+
+        let mut package: Option<XlsxPackage> = None;
+        let sheets = match format {
+            WorkbookFormat::Csv | WorkbookFormat::Tsv => {
+                let default_delimiter = if format == WorkbookFormat::Tsv {
+                    b'\t'
+                } else {
+                    b','
+                };
+                let delimiter = options.csv.delimiter.unwrap_or(default_delimiter);
+
+                let mut bytes = vec![];
+                reader.read_to_end(&mut bytes).map_err(|err| Error::Open {
+                    filepath: path.to_owned(),
+                    reason: err.to_string(),
+                })?;
+                let range = read_delimited(&bytes, delimiter, &options.csv).map_err(|reason| {
+                    Error::Open {
+                        filepath: path.to_owned(),
+                        reason,
+                    }
+                })?;
+                let sheet = Path::new(path)
+                    .file_stem()
+                    .map(|x| x.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                WorkbookSheets::Delimited { sheet, range }
+            }
+            WorkbookFormat::Xlsx => {
+                // shared by calamine and package not to be read twice
+                let mut bytes = vec![];
+                reader.read_to_end(&mut bytes).map_err(|err| Error::Open {
+                    filepath: path.to_owned(),
+                    reason: err.to_string(),
+                })?;
+                let bytes: Rc<[u8]> = bytes.into();
+                package = Some(
+                    XlsxPackage::new(bytes.clone())
+                        .map_err(|err| Error::from_package_open(path, err))?,
+                );
+                let reader: Box<dyn ReadSeek + 'a> = Box::new(Cursor::new(bytes));
+                WorkbookSheets::Sheets(Box::new(
+                    open_workbook_as(reader, format).map_err(|err| Error::from_open(path, err))?,
+                ))
+            }
+            _ => {
+                let reader: Box<dyn ReadSeek + 'a> = Box::new(reader);
+                WorkbookSheets::Sheets(Box::new(
+                    open_workbook_as(reader, format).map_err(|err| Error::from_open(path, err))?,
+                ))
+            }
+        };
+
+        let ret = Workbook {
+            sheets,
+            selected: selected.map(|x| x.to_owned()),
+            package,
+        };
+        if let Some(selected) = &ret.selected {
+            if !ret.all_sheet_names().contains(selected) {
+                return Err(Error::SheetNotFound {
+                    filepath: path.to_owned(),
+                    sheet: selected.to_owned(),
+                });
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// sheet names to compare, in workbook order
+    pub fn sheet_names(&self) -> Vec<String> {
+        match &self.selected {
+            Some(selected) => vec![selected.to_owned()],
+            None => self.all_sheet_names(),
+        }
+    }
+
+    /// whether only one sheet is compared: csv / tsv file or sheet selected by `#`
+    pub fn is_single_sheet(&self) -> bool {
+        self.selected.is_some() || matches!(self.sheets, WorkbookSheets::Delimited { .. })
+    }
+
+    /// 0-based sheet tab position among all sheets, regardless of sheets selected
+    pub fn sheet_position(&self, sheet: &str) -> Option<usize> {
+        self.all_sheet_names().iter().position(|x| x == sheet)
+    }
+
+    /// sheet tab visibility. csv / tsv is always visible
+    pub fn sheet_visibility(&self, sheet: &str) -> SheetVisibility {
+        match &self.sheets {
+            WorkbookSheets::Sheets(sheets) => sheets
+                .sheets_metadata()
+                .iter()
+                .find(|x| x.name == sheet)
+                .map_or(SheetVisibility::Visible, |x| x.visible.into()),
+            WorkbookSheets::Delimited { .. } => SheetVisibility::Visible,
+        }
+    }
+
+    /// read cell values in sheet
+    pub fn worksheet_range(&mut self, sheet: &str) -> Result<Range<Data>, calamine::Error> {
+        match &mut self.sheets {
+            WorkbookSheets::Sheets(sheets) => sheets.worksheet_range(sheet),
+            WorkbookSheets::Delimited { range, .. } => Ok(range.clone()),
+        }
+    }
+
+    /// read cell formulas in sheet
+    pub fn worksheet_formula(&mut self, sheet: &str) -> Result<Range<String>, calamine::Error> {
+        match &mut self.sheets {
+            WorkbookSheets::Sheets(sheets) => sheets.worksheet_formula(sheet),
+            WorkbookSheets::Delimited { .. } => Ok(Range::empty()),
+        }
+    }
+
+    /// merged cell ranges in sheet. only xlsx / xls have them
+    pub fn merged_regions(&mut self, sheet: &str) -> Result<Vec<CellRange>, calamine::Error> {
+        let WorkbookSheets::Sheets(sheets) = &mut self.sheets else {
+            return Ok(vec![]);
+        };
+        let dimensions = match sheets.as_mut() {
+            Sheets::Xlsx(xlsx) => xlsx
+                .worksheet_merge_cells(sheet)
+                .transpose()
+                .map_err(calamine::Error::Xlsx)?
+                .unwrap_or_default(),
+            Sheets::Xls(xls) => xls.worksheet_merge_cells(sheet).unwrap_or_default(),
+            _ => vec![],
+        };
+        // dimensions are 0-based
+        Ok(dimensions
+            .into_iter()
+            .map(|x| CellRange {
+                start_row: x.start.0 as usize + 1,
+                start_col: x.start.1 as usize + 1,
+                end_row: x.end.0 as usize + 1,
+                end_col: x.end.1 as usize + 1,
+            })
+            .collect())
+    }
+
+    /// cell comments by 0-based (row, col) as `author: text`. only xlsx has them
+    pub fn comments(&mut self, sheet: &str) -> Result<HashMap<(u32, u32), String>, PackageError> {
+        match &mut self.package {
+            Some(package) => package.comments(sheet),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// defined names. only xlsx has their scopes, and the others are all workbook-scoped
+    pub fn defined_names(&mut self) -> Result<Vec<DefinedName>, PackageError> {
+        if let Some(package) = &mut self.package {
+            return package.defined_names();
+        }
+        match &self.sheets {
+            WorkbookSheets::Sheets(sheets) => Ok(sheets
+                .defined_names()
+                .iter()
+                .map(|(name, reference)| DefinedName {
+                    name: name.to_owned(),
+                    scope: None,
+                    reference: reference.to_owned(),
+                })
+                .collect()),
+            WorkbookSheets::Delimited { .. } => Ok(vec![]),
+        }
+    }
+
+    /// excel tables. only xlsx has them
+    pub fn tables(&mut self) -> Result<Vec<Table>, PackageError> {
+        match &mut self.package {
+            Some(package) => package.tables(),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// cell styles of sheet. only xlsx has them
+    pub fn cell_styles(&mut self, sheet: &str) -> Result<SheetStyles, PackageError> {
+        match &mut self.package {
+            Some(package) => package.cell_styles(sheet),
+            None => Ok(SheetStyles::default()),
+        }
+    }
+
+    /// all sheet names in workbook order
+    fn all_sheet_names(&self) -> Vec<String> {
+        match &self.sheets {
+            WorkbookSheets::Sheets(sheets) => sheets.sheet_names(),
+            WorkbookSheets::Delimited { sheet, .. } => vec![sheet.to_owned()],
+        }
+    }
+}
+
+/// split `book.xlsx#Sheet1` into file path and sheet name
+///
+/// an existing file path is kept as it is even if it contains `#`
+fn split_sheet_selector(filepath: &str) -> (&str, Option<&str>) {
+    if fs::metadata(filepath).is_ok() {
+        return (filepath, None);
+    }
+    match filepath.rsplit_once('#') {
+        Some((path, sheet)) if !sheet.is_empty() => (path, Some(sheet)),
+        _ => (filepath, None),
+    }
+}
+
+/// open workbook as specified format
+fn open_workbook_as<RS: Read + Seek>(
+    reader: RS,
+    format: WorkbookFormat,
+) -> Result<Sheets<RS>, calamine::Error> {
+    let ret = match format {
+        WorkbookFormat::Xlsx => Sheets::Xlsx(open_workbook_from_rs::<Xlsx<_>, _>(reader)?),
+        WorkbookFormat::Xlsb => Sheets::Xlsb(open_workbook_from_rs::<Xlsb<_>, _>(reader)?),
+        WorkbookFormat::Xls => Sheets::Xls(open_workbook_from_rs::<Xls<_>, _>(reader)?),
+        WorkbookFormat::Ods => Sheets::Ods(open_workbook_from_rs::<Ods<_>, _>(reader)?),
+        WorkbookFormat::Csv | WorkbookFormat::Tsv => {
+            return Err(calamine::Error::Msg("csv / tsv is not a workbook"))
+        }
+    };
+    Ok(ret)
+}
@@ -1 +1,3 @@
 pub mod core;
+
+pub use core::error::Error;
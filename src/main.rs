@@ -1,18 +1,94 @@
 use std::{env, fs};
 
-use sheets_diff::core::{diff::Diff, unified_format::unified_diff};
+use sheets_diff::core::{
+    address::parse_sheet_range,
+    diff::Diff,
+    options::{DiffOptions, SheetPattern},
+    unified_format::{unified_diff, unified_diff_combined},
+};
+
+/// show value and formula diffs of the same cell in one hunk
+const COMBINED_FLAG: &str = "--combined";
+/// compare cell formatting too
+const STYLES_FLAG: &str = "--styles";
+/// compare only sheets matching name, glob or `/regex/`. repeatable
+const SHEET_FLAG: &str = "--sheet";
+/// skip sheets matching name, glob or `/regex/`. repeatable
+const EXCLUDE_SHEET_FLAG: &str = "--exclude-sheet";
+/// compare only cells in range such as `Summary!A1:F200`. repeatable
+const RANGE_FLAG: &str = "--range";
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let combined = args.iter().any(|x| x == COMBINED_FLAG);
+    args.retain(|x| x != COMBINED_FLAG);
+    let compare_styles = args.iter().any(|x| x == STYLES_FLAG);
+    args.retain(|x| x != STYLES_FLAG);
+    let options = DiffOptions {
+        compare_styles,
+        ..options(&mut args)
+    };
     let (old_filepath, new_filepath) = filepaths(args.as_ref());
 
-    let diff = Diff::new(old_filepath, new_filepath);
-    println!("{}", unified_diff(&diff).format());
+    let diff = match Diff::try_new_with_options(old_filepath, new_filepath, &options) {
+        Ok(diff) => diff,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    diff.sheet_errors
+        .iter()
+        .for_each(|err| eprintln!("{}", err));
+    let unified_diff = if combined {
+        unified_diff_combined(&diff)
+    } else {
+        unified_diff(&diff)
+    };
+    println!("{}", unified_diff.format());
+}
+
+/// take flags with value out of args
+fn options(args: &mut Vec<String>) -> DiffOptions {
+    let mut options = DiffOptions::default();
+    let mut rest: Vec<String> = vec![];
+    let mut iter = args.drain(..);
+    while let Some(arg) = iter.next() {
+        let flag = arg.as_str();
+        if ![SHEET_FLAG, EXCLUDE_SHEET_FLAG, RANGE_FLAG].contains(&flag) {
+            rest.push(arg);
+            continue;
+        }
+        let Some(value) = iter.next() else {
+            eprintln!("Value missing for {}", flag);
+            std::process::exit(1);
+        };
+        let ret = match flag {
+            SHEET_FLAG => value
+                .parse::<SheetPattern>()
+                .map(|x| options.sheets.include.push(x)),
+            EXCLUDE_SHEET_FLAG => value
+                .parse::<SheetPattern>()
+                .map(|x| options.sheets.exclude.push(x)),
+            _ => parse_sheet_range(&value)
+                .map(|(sheet, range)| options.sheets.ranges.entry(sheet).or_default().push(range)),
+        };
+        if let Err(err) = ret {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+    drop(iter);
+    *args = rest;
+    options
 }
 
-fn filepaths<'a>(args: &'a Vec<String>) -> (&'a str, &'a str) {
+fn filepaths(args: &[String]) -> (&str, &str) {
     if args.len() != 3 {
-        eprintln!("Usage: {} <file1> <file2>", args[0]);
+        eprintln!(
+            "Usage: {} [{}] [{}] [{} <pattern>]... [{} <pattern>]... [{} <sheet!range>]... <file1> <file2>",
+            args[0], COMBINED_FLAG, STYLES_FLAG, SHEET_FLAG, EXCLUDE_SHEET_FLAG, RANGE_FLAG
+        );
         std::process::exit(1);
     }
 
@@ -27,6 +103,10 @@ fn filepaths<'a>(args: &'a Vec<String>) -> (&'a str, &'a str) {
     (old_filepath, new_filepath)
 }
 
+/// file path may have `#` suffix to select sheet, e.g. `book.xlsx#Sheet1`
 fn is_valid_filepath(filepath: &str) -> bool {
     fs::metadata(filepath).is_ok()
+        || filepath
+            .rsplit_once('#')
+            .is_some_and(|(filepath, _)| fs::metadata(filepath).is_ok())
 }
@@ -1,6 +1,28 @@
 #[cfg(test)]
 mod tests {
-    use sheets_diff::core::unified_format::unified_diff;
+    use std::{
+        collections::HashMap,
+        fs::{self, File},
+        io::Cursor,
+    };
+
+    use chrono::{NaiveDateTime, NaiveTime};
+    use sheets_diff::{
+        core::{
+            address::{address_to_cell_pos, col_to_letters, parse_sheet_range, CellRange},
+            diff::{
+                CellDiffKind, CellType, CellValue, DefinedNameDiffKind, Diff, MergedCellDiffKind,
+            },
+            options::{
+                ColumnAlignment, ComparisonPolicy, CsvOptions, DiffOptions, FormulaComparison,
+                KeyColumn, NumericTolerance, SheetPattern, SheetSelection, SheetTolerance,
+                TextComparison, UnicodeNormalization,
+            },
+            unified_format::{unified_diff, unified_diff_combined, FormattedUnifiedDiff},
+            workbook::WorkbookFormat,
+        },
+        Error,
+    };
 
     #[test]
     fn it_works() {
@@ -28,8 +50,6 @@ mod tests {
 @@ D10(10,4) formula @@
 - 1+1
 + 2*4
-@@ D11(11,4) formula @@
-+ 
 @@ D12(12,4) value @@
 + a123
 @@ D12(12,4) formula @@
@@ -38,8 +58,1089 @@ mod tests {
 + っｓ
 "#;
 
-        let diff = sheets_diff::core::diff::Diff::new(OLD_FILEPATH, NEW_FILEPATH);
+        let diff = Diff::new(OLD_FILEPATH, NEW_FILEPATH);
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn try_new_reports_unreadable_workbooks() {
+        const FILEPATH: &str = "tests/fixtures/file1.xlsx";
+
+        let err = Diff::try_new("tests/fixtures/missing.xlsx", FILEPATH).unwrap_err();
+        assert!(matches!(err, Error::Open { .. }));
+
+        let err = Diff::try_new(FILEPATH, "tests/fixtures/corrupt.xlsx").unwrap_err();
+        assert!(matches!(err, Error::CorruptArchive { .. }));
+
+        let err = Diff::try_new(FILEPATH, "Cargo.toml").unwrap_err();
+        assert_eq!(
+            err,
+            Error::UnsupportedFormat {
+                filepath: "Cargo.toml".to_owned()
+            }
+        );
+
+        let diff = Diff::try_new(FILEPATH, FILEPATH).unwrap();
+        assert!(!diff.is_partial());
+    }
+
+    #[test]
+    fn cross_format_diff() {
+        const OLD_FILEPATH: &str = "tests/fixtures/file1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/file1.ods";
+
+        const EXPECT: &str = r#"--- tests/fixtures/file1.xlsx [Sheet1]
++++ tests/fixtures/file1.ods [Sheet1]
+@@ D10(10,4) formula to constant @@
+- =1+1
++ 2
+"#;
+
+        let diff = Diff::new(OLD_FILEPATH, NEW_FILEPATH);
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn detect_format_by_magic_bytes() {
+        let mut xlsx = File::open("tests/fixtures/file1.xlsx").unwrap();
+        assert_eq!(
+            WorkbookFormat::from_magic_bytes(&mut xlsx),
+            Some(WorkbookFormat::Xlsx)
+        );
+
+        let mut ods = File::open("tests/fixtures/file1.ods").unwrap();
+        assert_eq!(
+            WorkbookFormat::from_magic_bytes(&mut ods),
+            Some(WorkbookFormat::Ods)
+        );
+
+        let mut corrupt = File::open("tests/fixtures/corrupt.xlsx").unwrap();
+        assert_eq!(WorkbookFormat::from_magic_bytes(&mut corrupt), None);
+    }
+
+    #[test]
+    fn csv_diff_with_sheet() {
+        const OLD_FILEPATH: &str = "tests/fixtures/file1.xlsx#Sheet1";
+        const NEW_FILEPATH: &str = "tests/fixtures/sheet1.csv";
+
+        const EXPECT: &str = r#"--- tests/fixtures/file1.xlsx#Sheet1 [Sheet1]
++++ tests/fixtures/sheet1.csv [sheet1]
+@@ C3(3,3) value @@
+- 3
++ 30
+@@ D10(10,4) formula to constant @@
+- =1+1
++ 2
+"#;
+
+        let diff = Diff::new(OLD_FILEPATH, NEW_FILEPATH);
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        let err = Diff::try_new("tests/fixtures/file1.xlsx#Missing", NEW_FILEPATH).unwrap_err();
+        assert!(matches!(err, Error::SheetNotFound { .. }));
+    }
+
+    #[test]
+    fn tsv_diff_with_encoding() {
+        const OLD_FILEPATH: &str = "tests/fixtures/sheet1.csv";
+        const NEW_FILEPATH: &str = "tests/fixtures/sheet1.tsv";
+
+        const EXPECT: &str = r#"--- tests/fixtures/sheet1.csv [sheet1]
++++ tests/fixtures/sheet1.tsv [sheet1]
+@@ B2(2,2) value @@
+- 2
++ 今日は世界
+@@ D10(10,4) value @@
+- 2
+"#;
+
+        let options = DiffOptions {
+            csv: CsvOptions {
+                encoding: "shift_jis".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn csv_zero_padded_numbers() {
+        const OLD_FILEPATH: &str = "tests/fixtures/ids1.csv";
+        const NEW_FILEPATH: &str = "tests/fixtures/ids2.csv";
+
+        const EXPECT: &str = r#"--- tests/fixtures/ids1.csv [ids1]
++++ tests/fixtures/ids2.csv [ids2]
+@@ A1(1,1) value @@
+- 007
++ 7
+@@ D1(1,4) value @@
+- -01
++ -1
+"#;
+
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn diff_from_bytes() {
+        const EXPECT: &str = r#"--- book.xlsx#Sheet1 [Sheet1]
++++ export.csv [export]
+@@ C3(3,3) value @@
+- 3
++ 30
+@@ D10(10,4) formula to constant @@
+- =1+1
++ 2
+"#;
+
+        let old = fs::read("tests/fixtures/file1.xlsx").unwrap();
+        let new = fs::read("tests/fixtures/sheet1.csv").unwrap();
+
+        let diff = Diff::from_bytes(&old, &new, ("book.xlsx#Sheet1", "export.csv")).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn align_rows() {
+        const OLD_FILEPATH: &str = "tests/fixtures/rows1.csv";
+        const NEW_FILEPATH: &str = "tests/fixtures/rows2.csv";
+
+        const EXPECT: &str = r#"--- tests/fixtures/rows1.csv [rows1]
++++ tests/fixtures/rows2.csv [rows2]
+@@ 2:2 row inserted @@
++ 0, avocado, 5
+@@ 5:5 row deleted @@
+- 4, durian, 40
+@@ C4(4,3) -> C5(5,3) value @@
+- 30
++ 35
+"#;
+
+        let options = DiffOptions {
+            align_rows: true,
+            ..Default::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn align_many_changed_rows() {
+        // every other row changed and a row inserted every 500 rows
+        let mut old = String::new();
+        let mut new = String::new();
+        for i in 0..5000 {
+            old.push_str(&format!("{},item {},{}\n", i, i, i * 10));
+            if i % 500 == 0 {
+                new.push_str(&format!("new {},inserted,0\n", i));
+            }
+            let value = if i % 2 == 0 { i * 10 } else { i * 10 + 1 };
+            new.push_str(&format!("{},item {},{}\n", i, i, value));
+        }
+
+        let options = DiffOptions {
+            align_rows: true,
+            ..DiffOptions::default()
+        };
+        let diff = Diff::from_readers_with_options(
+            Cursor::new(old.as_bytes()),
+            Cursor::new(new.as_bytes()),
+            ("old.csv", "new.csv"),
+            &options,
+        )
+        .unwrap();
+        let sheet_cell_diff = &diff.cell_diffs[0];
+        assert_eq!(sheet_cell_diff.rows.len(), 10);
+        assert_eq!(sheet_cell_diff.cells.len(), 2500);
+        assert!(sheet_cell_diff.cells.iter().all(|x| x.col == 3));
+
+        // rows all found on both sides but in another order
+        let old: String = (0..5000).map(|i| format!("{}\n", i % 50)).collect();
+        let new: String = (0..5000).map(|i| format!("{}\n", i * 7 % 50)).collect();
+        let diff = Diff::from_readers_with_options(
+            Cursor::new(old.as_bytes()),
+            Cursor::new(new.as_bytes()),
+            ("old.csv", "new.csv"),
+            &options,
+        )
+        .unwrap();
+        assert!(!diff.cell_diffs[0].rows.is_empty());
+    }
+
+    #[test]
+    fn align_columns() {
+        const OLD_FILEPATH: &str = "tests/fixtures/cols1.csv";
+        const NEW_FILEPATH: &str = "tests/fixtures/cols2.csv";
+
+        const EXPECT: &str = r#"--- tests/fixtures/cols1.csv [cols1]
++++ tests/fixtures/cols2.csv [cols2]
+@@ D:D -> B:B column moved @@
+- qty
++ qty
+@@ D:D column inserted @@
++ VAT
+@@ D3(3,4) -> B3(3,2) value @@
+- 20
++ 25
+"#;
+
+        let options = DiffOptions {
+            align_columns: Some(ColumnAlignment::Header),
+            ..Default::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
         let target = unified_diff(&diff).format();
         assert_eq!(format!("{}", target), EXPECT);
     }
+
+    #[test]
+    fn match_records_by_key() {
+        const OLD_FILEPATH: &str = "tests/fixtures/records1.csv";
+        const NEW_FILEPATH: &str = "tests/fixtures/records2.csv";
+
+        const EXPECT: &str = r#"--- tests/fixtures/records1.csv [records1]
++++ tests/fixtures/records2.csv [records2]
+@@ [1001] qty changed @@
+- 10
++ 12
+@@ [1004] 5:5 record removed @@
+- 1004, durian, 40
+@@ [1006] 4:4 record added @@
++ 1006, fig, 60
+@@ [1005] duplicate key @@
+- rows 6, 7
++ rows 6
+"#;
+
+        let options = DiffOptions {
+            keys: HashMap::from([(
+                "records1".to_owned(),
+                vec![KeyColumn::Header("CustomerId".to_owned())],
+            )]),
+            ..Default::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn records_without_key() {
+        const EXPECT: &str = r#"--- old.csv [old]
++++ new.csv [new]
+@@ [] 3:3 record removed @@
+- , orphan
+@@ [] 4:4 record added @@
++ , stray
+"#;
+
+        let old = "id,name\n1,apple\n,orphan\n,same\n";
+        let new = "id,name\n1,apple\n,same\n,stray\n";
+        let diff_with_key = |key: KeyColumn| {
+            let options = DiffOptions {
+                keys: HashMap::from([("old".to_owned(), vec![key])]),
+                ..Default::default()
+            };
+            Diff::from_readers_with_options(
+                Cursor::new(old.as_bytes()),
+                Cursor::new(new.as_bytes()),
+                ("old.csv", "new.csv"),
+                &options,
+            )
+            .unwrap()
+        };
+
+        let diff = diff_with_key(KeyColumn::Col(1));
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        // key col out of sheet is error rather than no diffs
+        let diff = diff_with_key(KeyColumn::Col(5));
+        assert!(matches!(
+            &diff.sheet_errors[..],
+            [Error::KeyNotFound { key, .. }, ..] if key == "E"
+        ));
+    }
+
+    #[test]
+    fn detect_renamed_sheets() {
+        const OLD_FILEPATH: &str = "tests/fixtures/renamed1.ods";
+        const NEW_FILEPATH: &str = "tests/fixtures/renamed2.ods";
+
+        const EXPECT: &str = r#"--- tests/fixtures/renamed1.ods (sheet names)
++++ tests/fixtures/renamed2.ods (sheet names)
+@@ sheet renamed (88% similar) @@
+- Summary
++ Q3 Summary
+- Old
++ Notes
+--- tests/fixtures/renamed1.ods [Summary]
++++ tests/fixtures/renamed2.ods [Q3 Summary]
+@@ B3(3,2) value @@
+- 20
++ 25
+"#;
+
+        let options = DiffOptions {
+            sheet_rename_similarity: Some(0.5),
+            ..Default::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn sheet_moves_and_visibility() {
+        const OLD_FILEPATH: &str = "tests/fixtures/sheets1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/sheets2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/sheets1.xlsx (sheet names)
++++ tests/fixtures/sheets2.xlsx (sheet names)
+@@ sheet moved 1 -> 3 @@
+- Alpha
++ Alpha
+@@ sheet visible -> hidden @@
+- Gamma
++ Gamma
+"#;
+
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        // positions are of tabs in whole workbook even when some sheets are left out
+        let options = DiffOptions {
+            sheets: SheetSelection {
+                exclude: vec![SheetPattern::Name("Beta".to_owned())],
+                ..SheetSelection::default()
+            },
+            ..DiffOptions::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn numeric_tolerance() {
+        const OLD_FILEPATH: &str = "tests/fixtures/tolerance1.csv";
+        const NEW_FILEPATH: &str = "tests/fixtures/tolerance2.csv";
+
+        const EXPECT: &str = r#"--- tests/fixtures/tolerance1.csv [tolerance1]
++++ tests/fixtures/tolerance2.csv [tolerance2]
+@@ B3(3,2) value @@
+- 1.5
++ 1.6
+@@ C4(4,3) value @@
+- 300
++ 301
+"#;
+
+        let options = DiffOptions {
+            tolerance: NumericTolerance {
+                absolute: 1e-6,
+                relative: 0.0,
+            },
+            sheet_tolerances: HashMap::from([(
+                "tolerance1".to_owned(),
+                SheetTolerance {
+                    tolerance: None,
+                    columns: HashMap::from([(
+                        3,
+                        NumericTolerance {
+                            absolute: 0.0,
+                            relative: 1e-3,
+                        },
+                    )]),
+                },
+            )]),
+            ..Default::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn comparison_policy() {
+        const OLD_FILEPATH: &str = "tests/fixtures/types1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/types2.xlsx";
+
+        const EXPECT_STRICT: &str = r#"--- tests/fixtures/types1.xlsx [Sheet1]
++++ tests/fixtures/types2.xlsx [Sheet1]
+@@ A1(1,1) type @@
+- float 1
++ text 1
+@@ A2(2,1) type @@
+- text 2
++ float 2
+@@ A3(3,1) value @@
+- 3
++ 4
+"#;
+        const EXPECT_TEXTUAL: &str = r#"--- tests/fixtures/types1.xlsx [Sheet1]
++++ tests/fixtures/types2.xlsx [Sheet1]
+@@ A3(3,1) value @@
+- 3
++ 4
+"#;
+
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT_STRICT);
+
+        let options = DiffOptions {
+            comparison: ComparisonPolicy::Textual,
+            ..Default::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT_TEXTUAL);
+    }
+
+    #[test]
+    fn typed_cell_values() {
+        const OLD_FILEPATH: &str = "tests/fixtures/values1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/values2.xlsx";
+
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let values: Vec<(Option<CellValue>, Option<CellValue>)> = diff.cell_diffs[0]
+            .cells
+            .iter()
+            .map(|x| (x.old_value.clone(), x.new_value.clone()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                (Some(CellValue::Bool(true)), Some(CellValue::Bool(false))),
+                (
+                    Some(CellValue::Float(1.5)),
+                    Some(CellValue::Error("#DIV/0!".to_owned()))
+                ),
+                (
+                    Some(CellValue::String("a".to_owned())),
+                    Some(CellValue::Empty)
+                ),
+            ]
+        );
+        let texts: Vec<Option<String>> = diff.cell_diffs[0]
+            .cells
+            .iter()
+            .map(|x| x.old.clone())
+            .collect();
+        let rendered: Vec<Option<String>> = values
+            .iter()
+            .map(|(old, _)| old.as_ref().map(|x| x.to_string()))
+            .collect();
+        assert_eq!(texts, rendered);
+    }
+
+    #[test]
+    fn iso_dates() {
+        const OLD_FILEPATH: &str = "tests/fixtures/dates1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/dates2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/dates1.xlsx [Sheet1]
++++ tests/fixtures/dates2.xlsx [Sheet1]
+@@ A2(2,1) value @@
+- 2024-01-31T12:00:00
++ 2024-01-31T18:00:00
+@@ A3(3,1) value @@
+- PT36H30M
++ PT37H30M
+@@ A4(4,1) value @@
+- 12:00:00
++ 18:00:00
+@@ A5(5,1) value @@
+- 2024-01-31
++ 2024-02-01
+"#;
+        const EXPECT_BY_DAY: &str = r#"--- tests/fixtures/dates1.xlsx [Sheet1]
++++ tests/fixtures/dates2.xlsx [Sheet1]
+@@ A3(3,1) value @@
+- PT36H30M
++ PT37H30M
+@@ A4(4,1) value @@
+- 12:00:00
++ 18:00:00
+@@ A5(5,1) value @@
+- 2024-01-31
++ 2024-02-01
+"#;
+
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        let options = DiffOptions {
+            compare_dates_by_day: true,
+            ..Default::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT_BY_DAY);
+
+        // values are typed, with new ones in 1904 epoch
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let values: Vec<(CellType, Option<CellValue>, Option<CellValue>)> = diff.cell_diffs[0]
+            .cells
+            .iter()
+            .map(|x| {
+                let cell_type = x.new_type.unwrap();
+                (cell_type, x.old_value.clone(), x.new_value.clone())
+            })
+            .collect();
+        let datetime = |text: &str| {
+            CellValue::DateTime(NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M").unwrap())
+        };
+        let time = |hour: u32| CellValue::Time(NaiveTime::from_hms_opt(hour, 0, 0).unwrap());
+        assert_eq!(
+            values,
+            vec![
+                (
+                    CellType::DateTime,
+                    Some(datetime("2024-01-31T12:00")),
+                    Some(datetime("2024-01-31T18:00"))
+                ),
+                (
+                    CellType::Duration,
+                    Some(CellValue::Duration(131_400_000)),
+                    Some(CellValue::Duration(135_000_000))
+                ),
+                (CellType::DateTime, Some(time(12)), Some(time(18))),
+                (
+                    CellType::DateTime,
+                    Some(datetime("2024-01-31T00:00")),
+                    Some(datetime("2024-02-01T00:00"))
+                ),
+            ]
+        );
+
+        // the same dates in 1900 and 1904 epochs are equal
+        let diff = Diff::try_new(OLD_FILEPATH, "tests/fixtures/dates1904.xlsx").unwrap();
+        assert!(diff.cell_diffs.is_empty());
+    }
+
+    #[test]
+    fn normalize_text() {
+        const OLD_FILEPATH: &str = "tests/fixtures/text1.csv";
+        const NEW_FILEPATH: &str = "tests/fixtures/text2.csv";
+
+        const EXPECT: &str = r#"--- tests/fixtures/text1.csv [text1]
++++ tests/fixtures/text2.csv [text2]
+@@ A6(6,1) value @@
+- cherry
++ berry
+"#;
+
+        let options = DiffOptions {
+            text: TextComparison {
+                trim: true,
+                collapse_whitespace: true,
+                ignore_case: true,
+                normalization: Some(UnicodeNormalization::Nfkc),
+            },
+            ..Default::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        assert_eq!(diff.cell_diffs[0].cells.len(), 5);
+    }
+
+    #[test]
+    fn normalize_formulas() {
+        const OLD_FILEPATH: &str = "tests/fixtures/formulas1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/formulas2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/formulas1.xlsx [Sheet1]
++++ tests/fixtures/formulas2.xlsx [Sheet1]
+@@ 1:1 row inserted @@
++ 0, 0
+"#;
+
+        let mut options = DiffOptions {
+            align_rows: true,
+            formula: FormulaComparison {
+                normalize: true,
+                relative_references: true,
+            },
+            ..Default::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        // shifted references differ unless compared as relative ones
+        options.formula.relative_references = false;
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        assert_eq!(diff.cell_diffs[0].cells.len(), 3);
+
+        // sheet name looking like cell reference is not converted
+        options.formula.relative_references = true;
+        let diff = Diff::try_new_with_options(
+            "tests/fixtures/formula_sheets1.xlsx",
+            "tests/fixtures/formula_sheets2.xlsx",
+            &options,
+        )
+        .unwrap();
+        let sheet_cell_diff = &diff.cell_diffs[0];
+        assert_eq!(sheet_cell_diff.rows.len(), 1);
+        assert!(sheet_cell_diff.cells.is_empty());
+
+        // whole col / row references and names in other case are equal
+        let diff = Diff::try_new_with_options(
+            "tests/fixtures/formula_refs1.xlsx",
+            "tests/fixtures/formula_refs2.xlsx",
+            &options,
+        )
+        .unwrap();
+        let sheet_cell_diff = &diff.cell_diffs[0];
+        assert_eq!(sheet_cell_diff.rows.len(), 1);
+        assert!(sheet_cell_diff.cells.is_empty());
+    }
+
+    #[test]
+    fn combined_value_and_formula() {
+        const OLD_FILEPATH: &str = "tests/fixtures/file1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/file2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/file1.xlsx (sheet names)
++++ tests/fixtures/file2.xlsx (sheet names)
+- Sheet1_2
++ Sheetzz
+--- tests/fixtures/file1.xlsx [Sheet1]
++++ tests/fixtures/file2.xlsx [Sheet1]
+@@ A1(1,1) value @@
+- 1
+@@ B2(2,2) value @@
+- 2
++ 今日は世界
+@@ B4(4,2) value @@
++ a
+@@ C6(6,3) value @@
++ hej
+@@ D10(10,4) formula @@
+- =1+1 (2)
++ =2*4 (8)
+@@ D12(12,4) formula @@
++ ="a"&123 (a123)
+@@ W55(55,23) value @@
++ っｓ
+"#;
+
+        let diff = Diff::new(OLD_FILEPATH, NEW_FILEPATH);
+        let target = unified_diff_combined(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn classify_cell_changes() {
+        const OLD_FILEPATH: &str = "tests/fixtures/results1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/results2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/results1.xlsx [Sheet1]
++++ tests/fixtures/results2.xlsx [Sheet1]
+@@ A1(1,1) value @@
+- 1
++ 2
+@@ B1(1,2) result @@
+- 2
++ 4
+@@ C1(1,3) value @@
+- 5
++ 6
+@@ D1(1,4) formula @@
+- =A1+1 (2)
++ =A1*2 (4)
+@@ E1(1,5) result @@
+- error #N/A
++ text #N/A
+"#;
+
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let target = unified_diff_combined(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+    }
+
+    #[test]
+    fn formula_replaced_by_constant() {
+        const OLD_FILEPATH: &str = "tests/fixtures/constants1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/constants2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/constants1.xlsx [Sheet1]
++++ tests/fixtures/constants2.xlsx [Sheet1]
+@@ B1(1,2) formula to constant @@
+- =A1*2
++ 2
+@@ B2(2,2) constant to formula @@
+- 5
++ =A1*5
+@@ C1(1,3) constant to formula @@
+- 3
++ =A1*3
+@@ C2(2,3) formula to constant @@
+- =A1
++ 1
+"#;
+
+        let mut diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        // constants between formulas are combined as constants too
+        let kinds: Vec<CellDiffKind> = diff.cell_diffs[0]
+            .combined_cells()
+            .into_iter()
+            .map(|x| x.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CellDiffKind::FormulaToConstant,
+                CellDiffKind::ConstantToFormula,
+                CellDiffKind::ConstantToFormula,
+                CellDiffKind::FormulaToConstant,
+            ]
+        );
+
+        diff.retain_cell_kinds(&[CellDiffKind::FormulaToConstant]);
+        let addrs: Vec<&str> = diff.cell_diffs[0]
+            .cells
+            .iter()
+            .map(|x| x.addr.as_str())
+            .collect();
+        assert_eq!(addrs, vec!["B1", "C2"]);
+    }
+
+    #[test]
+    fn far_column_address() {
+        const OLD_FILEPATH: &str = "tests/fixtures/address1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/address2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/address1.xlsx [Sheet1]
++++ tests/fixtures/address2.xlsx [Sheet1]
+@@ AAA1(1,703) value @@
+- 1
++ 2
+@@ XFD2(2,16384) value @@
+- x
++ y
+"#;
+
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        assert_eq!(col_to_letters(52), "AZ");
+        assert_eq!(col_to_letters(16384), "XFD");
+        assert_eq!(address_to_cell_pos("XFD1048576"), Some((1048576, 16384)));
+        assert_eq!(address_to_cell_pos("$B$3"), Some((3, 2)));
+        assert_eq!(address_to_cell_pos("XFE1"), None);
+        assert_eq!(address_to_cell_pos("A1048577"), None);
+
+        let range: CellRange = "F200:A1".parse().unwrap();
+        assert_eq!(range.to_string(), "A1:F200");
+        assert!(range.contains(200, 6) && !range.contains(201, 1));
+        let cols: CellRange = "B:C".parse().unwrap();
+        assert!(cols.contains(1048576, 3) && !cols.contains(1, 1));
+        assert!("A1:B".parse::<CellRange>().is_err());
+    }
+
+    #[test]
+    fn sheet_and_range_selection() {
+        const OLD_FILEPATH: &str = "tests/fixtures/selection1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/selection2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/selection1.xlsx (sheet names)
++++ tests/fixtures/selection2.xlsx (sheet names)
++ Temp1
+--- tests/fixtures/selection1.xlsx [Summary]
++++ tests/fixtures/selection2.xlsx [Summary]
+@@ A1(1,1) value @@
+- a1
++ a2
+@@ B2(2,2) value @@
+- b1
++ b2
+"#;
+
+        let (sheet, range) = parse_sheet_range("'Summary'!B2:A1").unwrap();
+        let options = DiffOptions {
+            sheets: SheetSelection {
+                include: vec!["Summary".parse().unwrap(), "Temp?".parse().unwrap()],
+                exclude: vec![
+                    SheetPattern::Name("Scratch".to_owned()),
+                    "/^Calc\\d$/".parse().unwrap(),
+                ],
+                ranges: HashMap::from([(sheet, vec![range, "C3".parse().unwrap()])]),
+            },
+            ..DiffOptions::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        let options = DiffOptions {
+            sheets: SheetSelection {
+                include: vec![SheetPattern::Regex("(".to_owned())],
+                ..SheetSelection::default()
+            },
+            ..DiffOptions::default()
+        };
+        let err = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap_err();
+        assert!(matches!(err, Error::InvalidSheetPattern { .. }));
+        // cell ranges are 1-based
+        let options = DiffOptions {
+            sheets: SheetSelection {
+                ranges: HashMap::from([(
+                    "Summary".to_owned(),
+                    vec![CellRange {
+                        start_row: 0,
+                        start_col: 1,
+                        end_row: 2,
+                        end_col: 2,
+                    }],
+                )]),
+                ..SheetSelection::default()
+            },
+            ..DiffOptions::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        assert!(matches!(
+            diff.sheet_errors.as_slice(),
+            [Error::InvalidCellRange { .. }]
+        ));
+    }
+
+    #[test]
+    fn merged_cells_diff() {
+        const OLD_FILEPATH: &str = "tests/fixtures/merged1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/merged2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/merged1.xlsx [Sheet1]
++++ tests/fixtures/merged2.xlsx [Sheet1]
+@@ A1 merge resized @@
+- A1:B1
++ A1:C1
+@@ B2 merge added @@
++ B2:C3
+@@ A4 merge removed @@
+- A4:B4
+"#;
+
+        let mut diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        let merged_cells = &diff.cell_diffs[0].merged_cells;
+        assert_eq!(merged_cells[0].kind, MergedCellDiffKind::Resized);
+        assert_eq!(
+            merged_cells[0].new,
+            Some("A1:C1".parse::<CellRange>().unwrap())
+        );
+        assert!(diff.cell_diffs[0].cells.is_empty());
+
+        // sheet with merge diffs only is kept
+        diff.retain_cell_kinds(&[CellDiffKind::Value]);
+        assert_eq!(diff.cell_diffs[0].merged_cells.len(), 3);
+    }
+
+    #[test]
+    fn comments_diff() {
+        const OLD_FILEPATH: &str = "tests/fixtures/comments1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/comments2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/comments1.xlsx [Sheet1]
++++ tests/fixtures/comments2.xlsx [Sheet1]
+@@ A1(1,1) comment @@
+- Alice: check header
++ Alice: check header again
+@@ A3(3,1) comment @@
+- Alice: Is this final?
++ Alice: Is this final? / Bob: Yes (resolved)
+@@ B2(2,2) comment @@
+- Bob: source?
+@@ B3(3,2) value, comment @@
+- 4
++ 5 // Bob: new note
+"#;
+
+        let mut diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let target = unified_diff_combined(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        diff.retain_cell_kinds(&[CellDiffKind::Comment]);
+        let addrs: Vec<&str> = diff.cell_diffs[0]
+            .cells
+            .iter()
+            .map(|x| x.addr.as_str())
+            .collect();
+        assert_eq!(addrs, vec!["A1", "A3", "B2", "B3"]);
+    }
+
+    #[test]
+    fn defined_names_diff() {
+        const OLD_FILEPATH: &str = "tests/fixtures/names1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/names2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/names1.xlsx (defined names)
++++ tests/fixtures/names2.xlsx (defined names)
+@@ Padded changed @@
+- SUM(Inputs!$B$1,1)
++ sum( Inputs!$B$1, 1 )
+@@ Region removed @@
+- Inputs!$B$2
+@@ TaxRate changed @@
+- Inputs!$B$1
++ Inputs!$B$3
+@@ Total added @@
++ SUM(Inputs!$B:$B)
+@@ 'My Calc'!Local changed @@
+- 'My Calc'!$A$1
++ 'My Calc'!$A$2
+"#;
+
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        let local = diff.defined_names.last().unwrap();
+        assert_eq!(local.scope.as_deref(), Some("My Calc"));
+        assert_eq!(local.kind, DefinedNameDiffKind::Changed);
+
+        // names scoped to excluded sheet are left out
+        let options = DiffOptions {
+            sheets: SheetSelection {
+                exclude: vec![SheetPattern::Name("My Calc".to_owned())],
+                ..SheetSelection::default()
+            },
+            ..DiffOptions::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        assert_eq!(diff.defined_names.len(), 4);
+
+        // relative references imply normalization as cell formulas do
+        let options = DiffOptions {
+            formula: FormulaComparison {
+                normalize: false,
+                relative_references: true,
+            },
+            ..DiffOptions::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        assert!(diff.defined_names.iter().all(|x| x.name != "Padded"));
+    }
+
+    #[test]
+    fn tables_diff() {
+        const OLD_FILEPATH: &str = "tests/fixtures/tables1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/tables2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/tables1.xlsx (tables)
++++ tests/fixtures/tables2.xlsx (tables)
+@@ Revenue table renamed, resized @@
+- Sales (Data!A1:D4)
++ Revenue (Data!A1:D5)
+@@ Revenue[Quantity] column renamed @@
+- Qty
++ Quantity
+@@ Revenue[Note] column removed @@
+- Note
+@@ Revenue[Region] column added @@
++ Region
+@@ Revenue [1002] 3:3 record removed @@
+- 1002, 3, 20, b
+@@ Revenue [1003] Price changed @@
+- 30
++ 35
+@@ Revenue [1004] 4:4 record added @@
++ 1004, 40, 1, EU
+@@ Revenue [1005] 5:5 record added @@
++ 1005, 50, 2, JP
+@@ Costs table removed @@
+- Costs (Data!F1:G3)
+@@ Totals [k2] Amount changed @@
+- 2
++ 5
+@@ Raw [c2] Qty changed @@
+- 2
++ 3
+@@ Staff table added @@
++ Staff (Data!F1:G3)
+"#;
+
+        let options = DiffOptions {
+            table_keys: HashMap::from([
+                ("Sales".to_owned(), vec!["ID".to_owned()]),
+                ("Totals".to_owned(), vec!["Key".to_owned()]),
+                ("Raw".to_owned(), vec!["Code".to_owned()]),
+            ]),
+            ..DiffOptions::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = FormattedUnifiedDiff {
+            content: unified_diff(&diff).format().content[..1].to_vec(),
+        };
+        assert_eq!(format!("{}", target), EXPECT);
+
+        // unchanged table is left out, and so is totals row of Totals table
+        assert_eq!(diff.tables.len(), 5);
+        assert!(diff.tables[0].is_renamed() && !diff.tables[0].is_moved());
+    }
+
+    #[test]
+    fn styles_diff() {
+        const OLD_FILEPATH: &str = "tests/fixtures/styles1.xlsx";
+        const NEW_FILEPATH: &str = "tests/fixtures/styles2.xlsx";
+
+        const EXPECT: &str = r#"--- tests/fixtures/styles1.xlsx [Report]
++++ tests/fixtures/styles2.xlsx [Report]
+@@ A1(1,1) style @@
+- size: 11; italic: false; color: theme 1
++ size: 14; italic: true; color: #FF0000
+@@ B2(2,2) style @@
+- number format: 0.00
++ number format: 0.0%
+@@ C3(3,3) style @@
+- fill: solid #FFFF00
++ fill: none
+@@ D4(4,4) style @@
+- left border: thin indexed 64; right border: thin indexed 64; bottom border: double #000000; horizontal: center; wrap: true
++ left border: none; right border: none; bottom border: none; horizontal: general; wrap: false
+@@ E5(5,5) value @@
++ z
+@@ E5(5,5) style @@
+- fill: none
++ fill: solid #FFFF00
+"#;
+
+        let options = DiffOptions {
+            compare_styles: true,
+            ..DiffOptions::default()
+        };
+        let diff = Diff::try_new_with_options(OLD_FILEPATH, NEW_FILEPATH, &options).unwrap();
+        let target = unified_diff(&diff).format();
+        assert_eq!(format!("{}", target), EXPECT);
+
+        // styles are not compared by default
+        let diff = Diff::try_new(OLD_FILEPATH, NEW_FILEPATH).unwrap();
+        assert!(diff.cell_diffs[0]
+            .cells
+            .iter()
+            .all(|x| x.kind != CellDiffKind::Style));
+    }
 }